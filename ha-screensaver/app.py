@@ -7,23 +7,78 @@ It displays the HA dashboard and switches to a photo slideshow after idle time.
 """
 
 import os
+import io
+import atexit
+import re
+import math
 import json
+import shutil
+import heapq
+import fcntl
+import calendar
+import fnmatch
+import hashlib
 import logging
+import queue
+import random
+import subprocess
+import tempfile
+import threading
 import time
 import urllib.parse
 import urllib.request
-from datetime import datetime
+import xml.etree.ElementTree as ET
+from contextlib import contextmanager
+from functools import lru_cache, wraps
+from datetime import date, datetime, timedelta, timezone
 from pathlib import Path
-from typing import List, Dict, Any, Optional
+from typing import List, Dict, Any, Callable, Iterator, Optional, Tuple
 
 try:
-    from PIL import Image
+    from PIL import Image, ImageCms, ImageFilter, ImageOps, IptcImagePlugin
     HAS_PILLOW = True
 except ImportError:
     HAS_PILLOW = False
 
+# HEIF/AVIF decoding plugins for Pillow (optional)
+HAS_HEIF = False
+HAS_AVIF = False
+if HAS_PILLOW:
+    try:
+        import pillow_heif
+        pillow_heif.register_heif_opener()
+        HAS_HEIF = True
+        if hasattr(pillow_heif, 'register_avif_opener'):
+            pillow_heif.register_avif_opener()
+            HAS_AVIF = True
+    except ImportError:
+        pass
+    HAS_AVIF = HAS_AVIF or 'AVIF' in Image.registered_extensions().values()
+
+try:
+    from watchdog.events import FileSystemEventHandler
+    from watchdog.observers import Observer
+    HAS_WATCHDOG = True
+except ImportError:
+    HAS_WATCHDOG = False
+
+try:
+    import websocket
+    HAS_WEBSOCKET = True
+except ImportError:
+    HAS_WEBSOCKET = False
+
+try:
+    import paho.mqtt.client as mqtt
+    HAS_MQTT = True
+except ImportError:
+    HAS_MQTT = False
+
 from flask import Flask, Response, jsonify, request, send_file, send_from_directory
 from flask_cors import CORS
+from werkzeug.security import safe_join
+
+HAS_FFPROBE = shutil.which('ffprobe') is not None
 
 logging.basicConfig(
     level=logging.INFO,
@@ -31,315 +86,3734 @@ logging.basicConfig(
 )
 logger = logging.getLogger(__name__)
 
-app = Flask(__name__, static_folder='static')
-CORS(app)
+app = Flask(__name__, static_folder='static')
+# Other sites may read the slideshow data but not change anything
+CORS(app, methods=['GET', 'HEAD', 'OPTIONS'])
+
+CONFIG_FILE = Path("/app/config.json")
+if not CONFIG_FILE.exists():
+    CONFIG_FILE = Path("config.json")
+
+DEFAULT_CONFIG = {
+    "home_assistant_url": "http://homeassistant:8123",
+    "photos_folder": "/media",
+    "photos_source": "media",
+    "max_folder_depth": 10,
+    "rendition_format": "jpeg",
+    "rendition_quality": 80,
+    "rendition_cache_mb": 500,
+    "video_enabled": True,
+    "video_max_seconds": 30,
+    "video_long_clip_action": "trim",
+    "geocoding_provider": "nominatim",
+    "geocoding_url": "",
+    "geocoding_database": "",
+    "geocoding_precision": 2,
+    "geocoding_label": "city",
+    "geocoding_retry_hours": 24,
+    "idle_timeout_seconds": 60,
+    "slide_interval_seconds": 5,
+    "photo_filters": [],
+    "photo_selection": "all",
+    "on_this_day_window": "day",
+    "on_this_day_min_photos": 5,
+    "shuffle_queue": "per_screen",
+    "shuffle_favorite_weight": 1,
+    "shuffle_rating_weight": 1,
+    "shuffle_recent_weight": 1,
+    "shuffle_recent_days": 30,
+    "duplicate_suppression": True,
+    "duplicate_distance": 6,
+    "trash_folder": "",
+    "photo_layout": "single",
+    "photo_fit": "contain",
+    "transition": "fade",
+    "transition_seconds": 1,
+    "show_caption": True,
+    "clock_position": "bottom-center",
+    "weather_entity": "",
+    "media_player_entity": "",
+    "media_player_sources": "",
+    "presence_entity": "",
+    "motion_entity": "",
+    "sleep_after_minutes": 30,
+    "sleep_mode": "black",
+    "mqtt_discovery": True,
+    "mqtt_host": "",
+    "mqtt_port": 1883,
+    "mqtt_username": "",
+    "mqtt_password": ""
+}
+
+# Options never sent to screens
+SECRET_CONFIG_KEYS = {'mqtt_password'}
+
+# ============================================================================
+# HELPER FUNCTIONS
+# ============================================================================
+
+def load_config() -> Dict[str, Any]:
+    """Load configuration from JSON file, or return defaults if file doesn't exist."""
+    try:
+        if CONFIG_FILE.exists():
+            with open(CONFIG_FILE, 'r') as f:
+                config = json.load(f)
+                return {**DEFAULT_CONFIG, **config}
+        return DEFAULT_CONFIG.copy()
+    except (json.JSONDecodeError, IOError) as e:
+        logger.error(f"Failed to load config: {e}")
+        return DEFAULT_CONFIG.copy()
+
+
+def public_config(config: Dict[str, Any]) -> Dict[str, Any]:
+    """The configuration without secrets, as sent to screens."""
+    return {key: value for key, value in config.items() if key not in SECRET_CONFIG_KEYS}
+
+
+def _gps_to_decimal(coords, ref) -> Optional[float]:
+    """Convert GPS EXIF coordinates (degrees, minutes, seconds) to decimal."""
+    if not coords or not ref:
+        return None
+    try:
+        degrees = float(coords[0])
+        minutes = float(coords[1])
+        seconds = float(coords[2])
+        decimal = degrees + minutes / 60 + seconds / 3600
+        if ref in ('S', 'W'):
+            decimal = -decimal
+        return decimal
+    except (TypeError, ValueError, ZeroDivisionError):
+        return None
+
+
+def _format_date(dt: datetime) -> str:
+    """Format a capture date the way the photo info overlay shows it."""
+    return f"{dt.strftime('%B')} {dt.day}, {dt.year}"
+
+
+def extract_exif(file_path: str) -> Dict[str, Any]:
+    """
+    Extract upright dimensions, orientation, date taken and raw GPS
+    coordinates from image EXIF data, plus caption, keywords, rating, people
+    and camera from EXIF, IPTC and embedded XMP. Uses Pillow's
+    format-independent EXIF API so HEIF/AVIF work like JPEG.
+    """
+    if not HAS_PILLOW:
+        return {}
+    try:
+        with Image.open(file_path) as img:
+            exif_data = img.getexif()
+            width, height = img.size
+            xmp = img.info.get('xmp') or img.info.get('XML:com.adobe.xmp')
+            try:
+                iptc = IptcImagePlugin.getiptcinfo(img) or {}
+            except Exception:
+                iptc = {}
+            small = upright_thumbnail(img, exif_data.get(274))
+
+        result = _exif_description(exif_data)
+        if small is not None:
+            result['phash'] = perceptual_hash(small)
+            result['saliency'] = saliency_point(small)
+        result.update(_iptc_description(iptc))
+        if xmp:
+            result.update(parse_xmp(xmp))
+
+        # Orientation (EXIF tag 274); values 5-8 are rotated by 90 degrees
+        orientation = exif_data.get(274)
+        if orientation in (5, 6, 7, 8):
+            width, height = height, width
+        result['width'] = width
+        result['height'] = height
+        if orientation in range(2, 9):
+            result['orientation'] = orientation
+
+        if not exif_data:
+            return result
+
+        # DateTimeOriginal (EXIF tag 36867) lives in the Exif sub-IFD (0x8769)
+        date_taken = exif_data.get_ifd(0x8769).get(36867)
+        if date_taken:
+            try:
+                dt = datetime.strptime(date_taken, "%Y:%m:%d %H:%M:%S")
+                result['date'] = _format_date(dt)
+                result['taken'] = dt.isoformat()
+            except (ValueError, TypeError):
+                pass
+
+        # GPSInfo (EXIF tag 34853) -- return raw coordinates for geocoding
+        gps_info = exif_data.get_ifd(34853)
+        if gps_info:
+            lat = _gps_to_decimal(gps_info.get(2), gps_info.get(1))
+            lng = _gps_to_decimal(gps_info.get(4), gps_info.get(3))
+            if lat is not None and lng is not None:
+                result['lat'] = lat
+                result['lng'] = lng
+
+        return result
+    except Exception:
+        return {}
+
+
+# Transpose that turns a photo upright, by EXIF orientation
+ORIENTATION_TRANSPOSE = {
+    2: 'FLIP_LEFT_RIGHT', 3: 'ROTATE_180', 4: 'FLIP_TOP_BOTTOM',
+    5: 'TRANSPOSE', 6: 'ROTATE_270', 7: 'TRANSVERSE', 8: 'ROTATE_90'
+}
+
+
+def upright_thumbnail(img, orientation: Optional[int] = None, size: int = 64):
+    """
+    Small upright grayscale copy of an image, squashed to size x size, for
+    hashing and saliency. Returns None if the image can't be decoded.
+    """
+    try:
+        img.draft('L', (size * 2, size * 2))
+        small = img.convert('L').resize((size, size), Image.BILINEAR)
+        if orientation in ORIENTATION_TRANSPOSE:
+            small = small.transpose(getattr(Image.Transpose, ORIENTATION_TRANSPOSE[orientation]))
+        return small
+    except Exception:
+        return None
+
+
+def perceptual_hash(small) -> str:
+    """
+    64-bit difference hash (dHash) of an upright thumbnail as 16 hex digits.
+    Resized, recompressed and slightly edited copies of a photo hash within a
+    few bits of each other.
+    """
+    pixels = list(small.resize((9, 8), Image.BILINEAR).getdata())
+    bits = 0
+    for row in range(8):
+        for col in range(8):
+            bits = (bits << 1) | (pixels[row * 9 + col] > pixels[row * 9 + col + 1])
+    return f"{bits:016x}"
+
+
+def saliency_point(small) -> List[float]:
+    """
+    Guess where the subject of a photo is from an upright thumbnail: the
+    centroid of edge energy (detail draws the eye; sky, walls and blur have
+    little), pulled gently towards the centre. Returns normalised [x, y].
+    """
+    edges = small.filter(ImageFilter.FIND_EDGES)
+    width, height = edges.size
+    pixels = edges.load()
+    total = sum_x = sum_y = 0.0
+    # Skip the outermost pixels; FIND_EDGES leaves artefacts there
+    for y in range(1, height - 1):
+        for x in range(1, width - 1):
+            energy = pixels[x, y] ** 2
+            if not energy:
+                continue
+            dx, dy = x / width - 0.5, y / height - 0.5
+            energy *= 1.0 - (dx * dx + dy * dy)
+            total += energy
+            sum_x += energy * x
+            sum_y += energy * y
+    if not total:
+        return [0.5, 0.5]
+    return [round((sum_x / total + 0.5) / width, 3), round((sum_y / total + 0.5) / height, 3)]
+
+
+def _exif_description(exif_data) -> Dict[str, Any]:
+    """Caption, rating and camera from EXIF (the lowest-priority metadata source)."""
+    result: Dict[str, Any] = {}
+    # ImageDescription (270); cameras often fill it with blanks or a model name
+    description = str(exif_data.get(270) or '').strip()
+    if description:
+        result['caption'] = description
+    # Rating (0x4746), as written by Windows and most DAMs
+    rating = exif_data.get(0x4746)
+    if isinstance(rating, int):
+        result['rating'] = rating
+    # Make (271), Model (272); LensModel (0xA434) lives in the Exif sub-IFD
+    make = str(exif_data.get(271) or '').strip()
+    model = str(exif_data.get(272) or '').strip()
+    if model:
+        result['camera'] = model if model.lower().startswith(make.lower()) else f"{make} {model}".strip()
+    lens = str(exif_data.get_ifd(0x8769).get(0xA434) or '').strip()
+    if lens:
+        result['lens'] = lens
+    return result
+
+
+def _iptc_text(value) -> List[str]:
+    values = value if isinstance(value, list) else [value]
+    return [v.decode('utf-8', 'replace').strip() for v in values if isinstance(v, bytes)]
+
+
+def _iptc_description(iptc: Dict[Tuple[int, int], Any]) -> Dict[str, Any]:
+    """Caption/Abstract (2:120) and Keywords (2:25) from an IPTC-IIM block."""
+    result: Dict[str, Any] = {}
+    caption = ' '.join(_iptc_text(iptc.get((2, 120))))
+    if caption:
+        result['caption'] = caption
+    keywords = [k for k in _iptc_text(iptc.get((2, 25))) if k]
+    if keywords:
+        result['keywords'] = keywords
+    return result
+
+
+XMP_NAMESPACES = {
+    'rdf': 'http://www.w3.org/1999/02/22-rdf-syntax-ns#',
+    'dc': 'http://purl.org/dc/elements/1.1/',
+    'xmp': 'http://ns.adobe.com/xap/1.0/',
+    'tiff': 'http://ns.adobe.com/tiff/1.0/',
+    'aux': 'http://ns.adobe.com/exif/1.0/aux/',
+    'exifEX': 'http://cipa.jp/exif/1.0/',
+    'mwg-rs': 'http://www.metadataworkinggroup.com/schemas/regions/',
+    'MP': 'http://ns.microsoft.com/photo/1.2/',
+    'MPRI': 'http://ns.microsoft.com/photo/1.2/t/RegionInfo#',
+    'MPReg': 'http://ns.microsoft.com/photo/1.2/t/Region#',
+    'Iptc4xmpExt': 'http://iptc.org/std/Iptc4xmpExt/2008-02-29/',
+    'stArea': 'http://ns.adobe.com/xmp/sType/Area#',
+}
+XMP_LANG = '{http://www.w3.org/XML/1998/namespace}lang'
+
+
+def _xmp_values(root: ET.Element, prop: str) -> List[str]:
+    """
+    Collect every value of an XMP property, written either as an attribute
+    of rdf:Description or as an element holding text or an rdf:Bag/Seq/Alt.
+    For language alternatives the x-default entry comes first.
+    """
+    prefix, name = prop.split(':')
+    tag = f"{{{XMP_NAMESPACES[prefix]}}}{name}"
+    li = f"{{{XMP_NAMESPACES['rdf']}}}li"
+    values = []
+    for element in root.iter():
+        if tag in element.attrib:
+            values.append(element.attrib[tag])
+        for child in element.findall(tag):
+            items = list(child.iter(li))
+            if not items:
+                values.append(child.text or '')
+                continue
+            items.sort(key=lambda item: item.get(XMP_LANG) != 'x-default')
+            values.extend(item.text or '' for item in items)
+    return [v.strip() for v in values if v and v.strip()]
+
+
+def _xmp_people(root: ET.Element) -> List[str]:
+    """Names of people tagged in the photo: MWG face regions, Windows Photo regions, IPTC."""
+    names = []
+    for region_list in root.iter(f"{{{XMP_NAMESPACES['mwg-rs']}}}RegionList"):
+        for region in region_list.iter(f"{{{XMP_NAMESPACES['rdf']}}}li"):
+            if (_xmp_values(region, 'mwg-rs:Type') or ['Face'])[0] == 'Face':
+                names.extend(_xmp_values(region, 'mwg-rs:Name'))
+    names.extend(_xmp_values(root, 'MPReg:PersonDisplayName'))
+    names.extend(_xmp_values(root, 'Iptc4xmpExt:PersonInImage'))
+    return list(dict.fromkeys(names))
+
+
+def _xmp_faces(root: ET.Element) -> List[List[float]]:
+    """
+    Face rectangles as normalised [centre x, centre y, width, height] from MWG
+    regions (centre-based stArea) and Windows Photo regions (top-left based
+    "x, y, w, h" rectangles), in the upright photo's coordinates.
+    """
+    faces = []
+    for region_list in root.iter(f"{{{XMP_NAMESPACES['mwg-rs']}}}RegionList"):
+        for region in region_list.iter(f"{{{XMP_NAMESPACES['rdf']}}}li"):
+            if (_xmp_values(region, 'mwg-rs:Type') or ['Face'])[0] != 'Face':
+                continue
+            try:
+                area = [float(_xmp_values(region, f'stArea:{k}')[0]) for k in ('x', 'y', 'w', 'h')]
+            except (IndexError, ValueError):
+                continue
+            faces.append(area)
+    for rectangle in _xmp_values(root, 'MPReg:Rectangle'):
+        try:
+            x, y, w, h = (float(v) for v in rectangle.split(','))
+        except ValueError:
+            continue
+        faces.append([x + w / 2, y + h / 2, w, h])
+    return [[round(v, 4) for v in face] for face in faces
+            if 0 <= face[0] <= 1 and 0 <= face[1] <= 1]
+
+
+def parse_xmp(data) -> Dict[str, Any]:
+    """
+    Extract caption, keywords, rating, people and camera from an XMP packet
+    (embedded in the photo or read from a sidecar). Returns only the fields
+    that are present; malformed packets yield {}.
+    """
+    if isinstance(data, str):
+        data = data.encode('utf-8')
+    start, end = data.find(b'<x:xmpmeta'), data.rfind(b'</x:xmpmeta>')
+    if start >= 0 and end > start:
+        data = data[start:end + len(b'</x:xmpmeta>')]
+    try:
+        root = ET.fromstring(data)
+    except ET.ParseError:
+        return {}
+
+    result: Dict[str, Any] = {}
+    caption = _xmp_values(root, 'dc:description')
+    if caption:
+        result['caption'] = caption[0]
+    keywords = list(dict.fromkeys(_xmp_values(root, 'dc:subject')))
+    if keywords:
+        result['keywords'] = keywords
+    rating = _xmp_values(root, 'xmp:Rating')
+    if rating:
+        try:
+            result['rating'] = int(float(rating[0]))
+        except ValueError:
+            pass
+    people = _xmp_people(root)
+    if people:
+        result['people'] = people
+    faces = _xmp_faces(root)
+    if faces:
+        result['faces'] = faces
+    make = (_xmp_values(root, 'tiff:Make') or [''])[0]
+    model = (_xmp_values(root, 'tiff:Model') or [''])[0]
+    if model:
+        result['camera'] = model if model.lower().startswith(make.lower()) else f"{make} {model}".strip()
+    lens = _xmp_values(root, 'exifEX:LensModel') or _xmp_values(root, 'aux:Lens')
+    if lens:
+        result['lens'] = lens[0]
+    return result
+
+
+def xmp_sidecar(file_path: Path) -> Optional[Path]:
+    """Find the XMP sidecar of a photo: IMG_1.jpg.xmp (digiKam, darktable) or IMG_1.xmp (Lightroom)."""
+    for candidate in (file_path.with_name(file_path.name + '.xmp'), file_path.with_suffix('.xmp'),
+                      file_path.with_name(file_path.name + '.XMP'), file_path.with_suffix('.XMP')):
+        if candidate.is_file():
+            return candidate
+    return None
+
+
+def read_xmp_sidecar(sidecar: Path) -> Dict[str, Any]:
+    try:
+        with open(sidecar, 'rb') as f:
+            return parse_xmp(f.read())
+    except OSError as e:
+        logger.warning(f"Cannot read XMP sidecar {sidecar}: {e}")
+        return {}
+
+
+def _parse_iso6709(value: str) -> Optional[Tuple[float, float]]:
+    """Parse an ISO 6709 location string such as '+37.7858-122.4064+000.000/'."""
+    match = re.match(r'([+-]\d+(?:\.\d+)?)([+-]\d+(?:\.\d+)?)', value or '')
+    if not match:
+        return None
+    return float(match.group(1)), float(match.group(2))
+
+
+def extract_video_metadata(file_path: str) -> Dict[str, Any]:
+    """
+    Read duration, creation date and location from a video container with
+    ffprobe. Returns the same fields as extract_exif() plus 'duration'.
+    """
+    if not HAS_FFPROBE:
+        return {}
+    try:
+        output = subprocess.run(
+            ['ffprobe', '-v', 'quiet', '-print_format', 'json', '-show_format', file_path],
+            capture_output=True, timeout=30, check=True
+        ).stdout
+        fmt = json.loads(output).get('format', {})
+    except Exception as e:
+        logger.warning(f"ffprobe failed for {file_path}: {e}")
+        return {}
+
+    result = {}
+    try:
+        result['duration'] = round(float(fmt['duration']), 2)
+    except (KeyError, TypeError, ValueError):
+        pass
+
+    tags = {k.lower(): v for k, v in fmt.get('tags', {}).items()}
+
+    # Apple's tag keeps the local date; creation_time is UTC
+    created = tags.get('com.apple.quicktime.creationdate') or tags.get('creation_time')
+    if created:
+        try:
+            dt = datetime.strptime(created[:10], "%Y-%m-%d")
+            result['date'] = _format_date(dt)
+            result['taken'] = created[:19] if len(created) >= 19 else dt.isoformat()
+        except ValueError:
+            pass
+
+    coords = _parse_iso6709(tags.get('com.apple.quicktime.location.iso6709') or tags.get('location'))
+    if coords:
+        result['lat'], result['lng'] = coords
+
+    return result
+
+
+# ============================================================================
+# REVERSE GEOCODING
+# ============================================================================
+
+GEOCACHE_FILE = Path("/app/geocache.json")
+GEOCACHE_VERSION = 2
+GEOCODING_MAX_ATTEMPTS = 5
+GEOCODING_RETRY_SECONDS = 60
+GEOCODING_SAVE_EVERY = 10
+GEOCODING_RECHECK_SECONDS = 3600
+
+# A place is a dict with any of these keys, most specific first
+PLACE_FIELDS = ('neighbourhood', 'city', 'region', 'country')
+
+
+class GeocacheStore:
+    """
+    Geocache shared by every server process.
+
+    Keys are "<provider>:<lat>,<lng>" with coordinates rounded to the
+    configured precision. Each entry holds the structured place the provider
+    returned (None when it found nothing) and when it was looked up, so the
+    label granularity can change without new lookups and negative results can
+    expire. Saving merges local changes into the file under an exclusive
+    flock and replaces it atomically, so concurrent writers never clobber
+    each other.
+    """
+
+    def __init__(self, path: Path):
+        self.path = path
+        self._lock = threading.Lock()
+        self._entries: Optional[Dict[str, Dict[str, Any]]] = None
+        self._dirty: Dict[str, Dict[str, Any]] = {}
+        self._purged: set = set()
+
+    @staticmethod
+    def key(provider: str, lat: float, lng: float, precision: int) -> str:
+        return f"{provider}:{lat:.{precision}f},{lng:.{precision}f}"
+
+    @contextmanager
+    def _file_lock(self):
+        self.path.parent.mkdir(parents=True, exist_ok=True)
+        with open(self.path.with_suffix('.lock'), 'w') as lock_file:
+            fcntl.flock(lock_file, fcntl.LOCK_EX)
+            try:
+                yield
+            finally:
+                fcntl.flock(lock_file, fcntl.LOCK_UN)
+
+    def _read_file(self) -> Dict[str, Dict[str, Any]]:
+        try:
+            with open(self.path, 'r') as f:
+                data = json.load(f)
+        except FileNotFoundError:
+            return {}
+        except Exception as e:
+            logger.warning(f"Ignoring unreadable geocache: {e}")
+            return {}
+
+        if data.get('version') == GEOCACHE_VERSION:
+            return data.get('entries', {})
+
+        # Version 1 was a flat {"lat,lng": "City, Country" | null} dict from
+        # Nominatim. Keep the names; drop failures so they are retried.
+        entries = {}
+        for coords, label in data.items():
+            if isinstance(label, str):
+                city, _, country = label.rpartition(', ')
+                place = {'city': city, 'country': country} if city else {'city': country}
+                entries[f"nominatim:{coords}"] = {'place': place, 'time': time.time()}
+        logger.info(f"Migrated {len(entries)} geocache entries")
+        return entries
+
+    def _ensure_loaded(self) -> Dict[str, Dict[str, Any]]:
+        if self._entries is None:
+            self._entries = self._read_file()
+        return self._entries
+
+    def get(self, key: str, negative_ttl: float) -> Tuple[bool, Optional[Dict[str, str]]]:
+        """Return (hit, place). Negative results older than the TTL count as misses."""
+        with self._lock:
+            entry = self._ensure_loaded().get(key)
+        if entry is None:
+            return False, None
+        if entry['place'] is None and time.time() - entry.get('time', 0) > negative_ttl:
+            return False, None
+        return True, entry['place']
+
+    def put(self, key: str, place: Optional[Dict[str, str]]) -> None:
+        entry = {'place': place, 'time': time.time()}
+        with self._lock:
+            self._ensure_loaded()[key] = entry
+            self._dirty[key] = entry
+            self._purged.discard(key)
+
+    def purge(self, negative_only: bool = False) -> List[str]:
+        """Remove all entries (or only negative ones) and return their keys."""
+        with self._lock:
+            entries = self._ensure_loaded()
+            keys = [k for k, e in entries.items() if not negative_only or e['place'] is None]
+            for key in keys:
+                del entries[key]
+                self._dirty.pop(key, None)
+            self._purged.update(keys)
+        self.save()
+        return keys
+
+    def stats(self) -> Dict[str, int]:
+        with self._lock:
+            entries = self._ensure_loaded()
+            negative = sum(1 for e in entries.values() if e['place'] is None)
+        return {'entries': len(entries), 'negative': negative}
+
+    def save(self) -> None:
+        """Merge local changes into the file and atomically replace it."""
+        with self._lock:
+            dirty, purged = dict(self._dirty), set(self._purged)
+            self._dirty.clear()
+            self._purged.clear()
+        if not dirty and not purged:
+            return
+
+        try:
+            with self._file_lock():
+                entries = self._read_file()
+                for key in purged:
+                    entries.pop(key, None)
+                entries.update(dirty)
+                tmp_file = self.path.with_suffix('.tmp')
+                with open(tmp_file, 'w') as f:
+                    json.dump({'version': GEOCACHE_VERSION, 'entries': entries}, f)
+                    f.flush()
+                    os.fsync(f.fileno())
+                os.replace(tmp_file, self.path)
+            with self._lock:
+                # Pick up entries other processes wrote, keeping newer local ones
+                entries.update(self._dirty)
+                for key in self._purged:
+                    entries.pop(key, None)
+                self._entries = entries
+        except Exception as e:
+            logger.warning(f"Failed to save geocache: {e}")
+            with self._lock:
+                for key, entry in dirty.items():
+                    self._dirty.setdefault(key, entry)
+                self._purged.update(purged - set(self._dirty))
+
+
+geocache = GeocacheStore(GEOCACHE_FILE)
+
+
+def format_place(place: Optional[Dict[str, str]], granularity: str) -> Optional[str]:
+    """
+    Build the overlay label for a place at the configured granularity:
+    neighbourhood ("Nørrebro, Copenhagen"), city ("Copenhagen, Denmark"),
+    region ("Capital Region, Denmark") or country ("Denmark"). Falls back to
+    the nearest coarser level when the provider didn't return the requested one.
+    """
+    if not place:
+        return None
+    if granularity == 'neighbourhood' and place.get('neighbourhood'):
+        parts = [place['neighbourhood'], place.get('city') or place.get('country')]
+    elif granularity == 'country':
+        parts = [place.get('country') or place.get('region') or place.get('city')]
+    else:
+        levels = PLACE_FIELDS[PLACE_FIELDS.index(granularity if granularity != 'neighbourhood' else 'city'):-1]
+        area = next((place[level] for level in levels if place.get(level)), None)
+        parts = [area, place.get('country')]
+    parts = [p for i, p in enumerate(parts) if p and p not in parts[:i]]
+    return ', '.join(parts) or None
+
+
+def _format_coords(lat: float, lng: float) -> str:
+    """Fallback: format coordinates as a human-readable string."""
+    lat_dir = 'N' if lat >= 0 else 'S'
+    lng_dir = 'E' if lng >= 0 else 'W'
+    return f"{abs(lat):.1f}\u00b0{lat_dir}, {abs(lng):.1f}\u00b0{lng_dir}"
+
+
+class GeocodingError(Exception):
+    """A lookup failed for a reason worth retrying (network, rate limit, server error)."""
+
+
+class GeocodingProvider:
+    """
+    Turns coordinates into a place dict (see PLACE_FIELDS).
+
+    reverse() returns None when the provider knows of no place at the
+    coordinates and raises GeocodingError for failures that may succeed on
+    retry. Online providers declare the minimum number of seconds between
+    requests; their results are kept in the geocache.
+    """
+
+    name = 'none'
+    online = False
+    min_interval = 0.0
+
+    def reverse(self, lat: float, lng: float) -> Optional[Dict[str, str]]:
+        return None
+
+
+def _fetch_json(url: str) -> Any:
+    req = urllib.request.Request(url, headers={
+        'User-Agent': 'HAScreensaver/1.1'
+    })
+    try:
+        with urllib.request.urlopen(req, timeout=10) as resp:
+            return json.loads(resp.read())
+    except Exception as e:
+        raise GeocodingError(str(e)) from e
+
+
+def _make_place(**fields: Optional[str]) -> Optional[Dict[str, str]]:
+    place = {k: v for k, v in fields.items() if v}
+    return place or None
+
+
+class NominatimProvider(GeocodingProvider):
+    """OpenStreetMap Nominatim, or a self-hosted Nominatim server."""
+
+    name = 'nominatim'
+    online = True
+
+    def __init__(self, base_url: str = ''):
+        self.base_url = (base_url or 'https://nominatim.openstreetmap.org').rstrip('/')
+        # The public server's usage policy allows one request per second
+        self.min_interval = 0.0 if base_url else 1.0
+
+    def reverse(self, lat: float, lng: float) -> Optional[Dict[str, str]]:
+        data = _fetch_json(
+            f"{self.base_url}/reverse?"
+            f"format=json&lat={lat}&lon={lng}&zoom=14&accept-language=en"
+        )
+        addr = data.get('address', {})
+        return _make_place(
+            neighbourhood=addr.get('neighbourhood') or addr.get('suburb') or addr.get('quarter'),
+            city=(addr.get('city') or addr.get('town') or
+                  addr.get('village') or addr.get('hamlet') or
+                  addr.get('municipality')),
+            region=addr.get('state') or addr.get('region') or addr.get('county'),
+            country=addr.get('country')
+        )
+
+
+class PhotonProvider(GeocodingProvider):
+    """Photon (komoot's OpenStreetMap geocoder), public or self-hosted."""
+
+    name = 'photon'
+    online = True
+
+    def __init__(self, base_url: str = ''):
+        self.base_url = (base_url or 'https://photon.komoot.io').rstrip('/')
+        self.min_interval = 0.0 if base_url else 1.0
+
+    def reverse(self, lat: float, lng: float) -> Optional[Dict[str, str]]:
+        data = _fetch_json(f"{self.base_url}/reverse?lat={lat}&lon={lng}&lang=en")
+        features = data.get('features') or []
+        if not features:
+            return None
+        props = features[0].get('properties', {})
+        return _make_place(
+            neighbourhood=props.get('district') or props.get('locality'),
+            city=props.get('city') or props.get('town') or props.get('village') or props.get('name'),
+            region=props.get('state') or props.get('county'),
+            country=props.get('country')
+        )
+
+
+OFFLINE_GEOCODER_DATABASE = Path("/app/data/cities15000.txt")
+OFFLINE_GEOCODER_COUNTRIES = Path("/app/data/countryInfo.txt")
+OFFLINE_GEOCODER_REGIONS = Path("/app/data/admin1CodesASCII.txt")
+OFFLINE_GEOCODER_MAX_KM = 100
+
+
+class OfflineGeocoder:
+    """
+    Reverse geocoder backed by a GeoNames cities dump (cities500/1000/5000/
+    15000.txt, tab-separated). Cities are bucketed into a 1-degree grid, and a
+    lookup searches rings of cells around the point until no closer city can
+    exist. The database is loaded lazily on first use; if it can't be read,
+    loading is tried again only after the retry period.
+    """
+
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._path: Optional[Path] = None
+        self._failed: Optional[Tuple[Path, float]] = None
+        self._grid: Dict[Tuple[int, int], List[Tuple[float, float, str, str, str]]] = {}
+        self._countries: Dict[str, str] = {}
+        self._regions: Dict[str, str] = {}
+
+    @staticmethod
+    def _load_names(path: Path, name_column: int) -> Dict[str, str]:
+        """Read a GeoNames code -> name table (countryInfo.txt, admin1CodesASCII.txt)."""
+        names = {}
+        try:
+            with open(path, 'r', encoding='utf-8') as f:
+                for line in f:
+                    if line.startswith('#'):
+                        continue
+                    fields = line.rstrip('\n').split('\t')
+                    if len(fields) > name_column:
+                        names[fields[0]] = fields[name_column]
+        except OSError:
+            logger.warning(f"GeoNames names not found: {path}")
+        return names
+
+    def _load(self, path: Path) -> None:
+        grid: Dict[Tuple[int, int], List[Tuple[float, float, str, str, str]]] = {}
+        count = 0
+        with open(path, 'r', encoding='utf-8') as f:
+            for line in f:
+                fields = line.rstrip('\n').split('\t')
+                if len(fields) < 11:
+                    continue
+                try:
+                    lat, lng = float(fields[4]), float(fields[5])
+                except ValueError:
+                    continue
+                grid.setdefault(self._cell(lat, lng), []).append(
+                    (lat, lng, fields[1], fields[8], f"{fields[8]}.{fields[10]}")
+                )
+                count += 1
+
+        self._grid, self._path = grid, path
+        self._countries = self._load_names(OFFLINE_GEOCODER_COUNTRIES, 4)
+        self._regions = self._load_names(OFFLINE_GEOCODER_REGIONS, 1)
+        logger.info(f"Loaded {count} places for offline geocoding from {path}")
+
+    @staticmethod
+    def _cell(lat: float, lng: float) -> Tuple[int, int]:
+        return int(math.floor(lat)), int(math.floor(lng))
+
+    @staticmethod
+    def _distance_km(lat1: float, lng1: float, lat2: float, lng2: float) -> float:
+        """Great-circle (haversine) distance."""
+        phi1, phi2 = math.radians(lat1), math.radians(lat2)
+        dphi = phi2 - phi1
+        dlambda = math.radians(lng2 - lng1)
+        a = math.sin(dphi / 2) ** 2 + math.cos(phi1) * math.cos(phi2) * math.sin(dlambda / 2) ** 2
+        return 6371 * 2 * math.asin(math.sqrt(a))
+
+    def check(self, database: Path) -> None:
+        """Report a missing database at startup instead of on every lookup."""
+        if not database.is_file():
+            logger.warning(f"Offline geocoding database not found: {database}")
+            with self._lock:
+                self._failed = (database, time.monotonic())
+
+    def reverse(self, lat: float, lng: float, database: Path,
+                retry_after: float) -> Optional[Dict[str, str]]:
+        """Return the nearest place within OFFLINE_GEOCODER_MAX_KM."""
+        with self._lock:
+            if self._path != database:
+                if (self._failed and self._failed[0] == database
+                        and time.monotonic() - self._failed[1] < retry_after):
+                    return None
+                try:
+                    self._load(database)
+                    self._failed = None
+                except OSError as e:
+                    logger.warning(f"Offline geocoding database unavailable: {e}")
+                    self._failed = (database, time.monotonic())
+                    return None
+
+        best, best_km = None, OFFLINE_GEOCODER_MAX_KM
+        lat_cell, lng_cell = self._cell(lat, lng)
+        # One degree of latitude is ~111 km; longitude cells narrow towards the
+        # poles, so widen the ring accordingly
+        lng_scale = max(math.cos(math.radians(lat)), 0.05)
+        max_ring = int(math.ceil(OFFLINE_GEOCODER_MAX_KM / (111 * lng_scale))) + 1
+        for ring in range(max_ring + 1):
+            if best is not None and (ring - 1) * 111 * lng_scale > best_km:
+                break
+            for dlat in range(-ring, ring + 1):
+                for dlng in range(-ring, ring + 1):
+                    if max(abs(dlat), abs(dlng)) != ring:
+                        continue
+                    cell = (lat_cell + dlat, (lng_cell + dlng + 180) % 360 - 180)
+                    for place in self._grid.get(cell, ()):
+                        km = self._distance_km(lat, lng, place[0], place[1])
+                        if km < best_km:
+                            best, best_km = place, km
+
+        if best is None:
+            return None
+        return _make_place(
+            city=best[2],
+            region=self._regions.get(best[4]),
+            country=self._countries.get(best[3], best[3])
+        )
+
+
+offline_geocoder = OfflineGeocoder()
+
+
+class OfflineProvider(GeocodingProvider):
+    """Nearest city from a local GeoNames database; fast enough to run inline."""
+
+    name = 'offline'
+
+    def __init__(self, database: str = '', retry_hours: float = 24):
+        self.database = Path(database or OFFLINE_GEOCODER_DATABASE)
+        self.retry_after = retry_hours * 3600
+        offline_geocoder.check(self.database)
+
+    def reverse(self, lat: float, lng: float) -> Optional[Dict[str, str]]:
+        return offline_geocoder.reverse(lat, lng, self.database, self.retry_after)
+
+
+def make_geocoding_provider(config: Dict[str, Any]) -> GeocodingProvider:
+    """Build the provider selected by the geocoding_* options."""
+    provider = config.get('geocoding_provider', 'nominatim')
+    if provider == 'nominatim':
+        return NominatimProvider(config.get('geocoding_url', ''))
+    if provider == 'photon':
+        return PhotonProvider(config.get('geocoding_url', ''))
+    if provider == 'offline':
+        return OfflineProvider(config.get('geocoding_database', ''),
+                               float(config.get('geocoding_retry_hours', 24)))
+    return GeocodingProvider()
+
+
+class GeocodingWorker:
+    """
+    Resolves photo coordinates in a background thread so indexing never waits
+    on the network.
+
+    Lookups are queued by geocache key and run one at a time at the provider's
+    rate limit. Failed lookups are retried with exponential backoff. Results
+    are collected and, every GEOCODING_SAVE_EVERY lookups or once no lookup
+    is due, saved to the geocache (so progress survives a restart) and
+    applied to the photo index in one batch, which pushes the new place names
+    to open screens. Until then photos show their coordinates. Places that
+    could not be found are looked up again once geocoding_retry_hours pass.
+    """
+
+    def __init__(self):
+        self.provider: GeocodingProvider = GeocodingProvider()
+        self.precision = 2
+        self.granularity = 'city'
+        self.negative_ttl = 24 * 3600.0
+        self._cond = threading.Condition()
+        self._queue: List[Tuple[float, str, float, float, int]] = []
+        self._pending = set()
+        self._resolved: Dict[str, Optional[str]] = {}
+        self._unsaved = 0
+        self._last_request = 0.0
+
+    def start(self, provider: GeocodingProvider, config: Dict[str, Any]) -> None:
+        self.provider = provider
+        self.precision = max(1, min(int(config.get('geocoding_precision', 2)), 4))
+        self.granularity = config.get('geocoding_label', 'city')
+        if self.granularity not in PLACE_FIELDS:
+            self.granularity = 'city'
+        self.negative_ttl = float(config.get('geocoding_retry_hours', 24)) * 3600
+        if provider.online:
+            threading.Thread(target=self._run, daemon=True, name='geocoding-worker').start()
+
+    @property
+    def signature(self) -> str:
+        """Identifies the settings a location label was produced with."""
+        return f"{self.provider.name}:{self.granularity}:{self.precision}"
+
+    def key(self, lat: float, lng: float) -> str:
+        return GeocacheStore.key(self.provider.name, lat, lng, self.precision)
+
+    def lookup(self, lat: float, lng: float) -> Tuple[bool, Optional[str]]:
+        """
+        Resolve coordinates without blocking: returns (True, name) when the
+        answer is known now, or queues a lookup and returns (False, None).
+        """
+        if not self.provider.online:
+            return True, format_place(self.provider.reverse(lat, lng), self.granularity)
+
+        key = self.key(lat, lng)
+        hit, place = geocache.get(key, self.negative_ttl)
+        if hit:
+            return True, format_place(place, self.granularity)
+        with self._cond:
+            if key not in self._pending:
+                self._pending.add(key)
+                heapq.heappush(self._queue, (time.time(), key, lat, lng, 0))
+                self._cond.notify()
+        return False, None
+
+    def pending(self) -> int:
+        with self._cond:
+            return len(self._pending)
+
+    def _next_job(self) -> Optional[Tuple[float, str, float, float, int]]:
+        """
+        Wait for the next due lookup. Returns None when no lookup is due and
+        results are waiting to be flushed, or after an idle recheck interval.
+        """
+        idle_until = time.time() + GEOCODING_RECHECK_SECONDS
+        with self._cond:
+            while not self._queue or self._queue[0][0] > time.time():
+                if self._unsaved:
+                    return None
+                if not self._queue and time.time() >= idle_until:
+                    return None
+                due = self._queue[0][0] if self._queue else idle_until
+                self._cond.wait(max(0.0, due - time.time()))
+            return heapq.heappop(self._queue)
+
+    def _flush(self) -> None:
+        """Save the geocache and apply the collected results to the photo index."""
+        with self._cond:
+            resolved, self._resolved = self._resolved, {}
+            self._unsaved = 0
+        geocache.save()
+        try:
+            photo_index.apply_locations(resolved)
+        except Exception as e:
+            logger.error(f"Error applying geocoding results: {e}")
+
+    def _run(self) -> None:
+        provider = self.provider
+        while True:
+            job = self._next_job()
+            if job is None and self._unsaved:
+                self._flush()
+                continue
+            if job is None:
+                # Re-queue photos whose negative results have expired
+                try:
+                    photo_index.refresh_locations()
+                except Exception as e:
+                    logger.error(f"Error refreshing photo locations: {e}")
+                continue
+            _, key, lat, lng, attempts = job
+
+            wait = self._last_request + provider.min_interval - time.time()
+            if wait > 0:
+                time.sleep(wait)
+            self._last_request = time.time()
+
+            try:
+                place = provider.reverse(lat, lng)
+            except GeocodingError as e:
+                attempts += 1
+                if attempts < GEOCODING_MAX_ATTEMPTS:
+                    delay = GEOCODING_RETRY_SECONDS * 2 ** (attempts - 1)
+                    logger.warning(
+                        f"Reverse geocoding failed for {key} ({e}), "
+                        f"retrying in {delay}s (attempt {attempts}/{GEOCODING_MAX_ATTEMPTS})"
+                    )
+                    with self._cond:
+                        heapq.heappush(self._queue, (time.time() + delay, key, lat, lng, attempts))
+                    continue
+                logger.warning(
+                    f"Reverse geocoding gave up on {key}: {e} "
+                    f"(retrying in {self.negative_ttl / 3600:g}h)"
+                )
+                place = None
+
+            geocache.put(key, place)
+            with self._cond:
+                self._pending.discard(key)
+                self._resolved[key] = format_place(place, self.granularity)
+                self._unsaved += 1
+                flush = self._unsaved >= GEOCODING_SAVE_EVERY
+            if flush:
+                self._flush()
+
+
+geocoding_worker = GeocodingWorker()
+
+
+# ============================================================================
+# EVENT STREAM
+# ============================================================================
+
+EVENTS_KEEPALIVE_SECONDS = 25
+EVENTS_QUEUE_SIZE = 100
+# Each stream holds one of the server's threads (see --threads in run.sh)
+EVENTS_MAX_STREAMS = 20
+
+
+class EventBus:
+    """
+    Fans server-side events out to every connected /api/events stream.
+    Each subscriber gets its own bounded queue; a client that stops reading
+    loses events rather than blocking the publisher.
+    """
+
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._subscribers: List[queue.Queue] = []
+
+    def subscribe(self, limit: Optional[int] = None) -> Optional[queue.Queue]:
+        """A new subscription, or None if limit subscribers are already connected."""
+        subscription: queue.Queue = queue.Queue(maxsize=EVENTS_QUEUE_SIZE)
+        with self._lock:
+            if limit is not None and len(self._subscribers) >= limit:
+                return None
+            self._subscribers.append(subscription)
+        return subscription
+
+    def unsubscribe(self, subscription: queue.Queue) -> None:
+        with self._lock:
+            if subscription in self._subscribers:
+                self._subscribers.remove(subscription)
+
+    def publish(self, event: str, data: Any) -> None:
+        with self._lock:
+            subscribers = list(self._subscribers)
+        for subscription in subscribers:
+            try:
+                subscription.put_nowait((event, data))
+            except queue.Full:
+                logger.warning(f"Dropping '{event}' event for a slow client")
+
+
+event_bus = EventBus()
+
+
+# ============================================================================
+# HOME ASSISTANT CONNECTION
+# ============================================================================
+
+HA_API_URL = 'http://supervisor/core/api'
+HA_WEBSOCKET_URL = 'ws://supervisor/core/websocket'
+HA_RECONNECT_MIN_SECONDS = 1
+HA_RECONNECT_MAX_SECONDS = 60
+HA_PING_SECONDS = 30
+HA_CALL_TIMEOUT_SECONDS = 5
+
+
+class HomeAssistantError(Exception):
+    """The Home Assistant WebSocket API refused or dropped the connection."""
+
+
+def ha_rest_request(path: str, body: Optional[Dict] = None, timeout: int = 5) -> Any:
+    """Call the Home Assistant REST API through the Supervisor proxy."""
+    supervisor_token = os.environ.get('SUPERVISOR_TOKEN', '')
+    if not supervisor_token:
+        raise HomeAssistantError("No SUPERVISOR_TOKEN available")
+    data = json.dumps(body).encode('utf-8') if body is not None else None
+    req = urllib.request.Request(f'{HA_API_URL}/{path}', data=data, headers={
+        'Authorization': f'Bearer {supervisor_token}',
+        'Content-Type': 'application/json'
+    })
+    with urllib.request.urlopen(req, timeout=timeout) as response:
+        return json.loads(response.read() or b'null')
+
+
+def _ha_timestamp(value: float) -> str:
+    return datetime.fromtimestamp(value, timezone.utc).isoformat()
+
+
+def _expand_state(entity_id: str, compressed: Dict) -> Dict:
+    """Turn a compressed `subscribe_entities` state into a regular state object."""
+    last_changed = _ha_timestamp(compressed['lc']) if 'lc' in compressed else None
+    last_updated = _ha_timestamp(compressed['lu']) if 'lu' in compressed else last_changed
+    return {
+        'entity_id': entity_id,
+        'state': compressed.get('s'),
+        'attributes': dict(compressed.get('a') or {}),
+        'last_changed': last_changed or last_updated,
+        'last_updated': last_updated
+    }
+
+
+def _apply_state_diff(state: Dict, diff: Dict) -> Dict:
+    """A copy of the state with a `subscribe_entities` change applied."""
+    state = {**state, 'attributes': dict(state.get('attributes') or {})}
+    additions = diff.get('+') or {}
+    if 's' in additions:
+        state['state'] = additions['s']
+    state['attributes'].update(additions.get('a') or {})
+    for name in (diff.get('-') or {}).get('a') or []:
+        state['attributes'].pop(name, None)
+    if 'lc' in additions:
+        state['last_changed'] = state['last_updated'] = _ha_timestamp(additions['lc'])
+    if 'lu' in additions:
+        state['last_updated'] = _ha_timestamp(additions['lu'])
+    return state
+
+
+class HomeAssistantClient:
+    """
+    Keeps one authenticated connection to the Home Assistant WebSocket API.
+
+    The entities the screensaver shows are followed with `subscribe_entities`,
+    which sends their states on connect and then only the changes to those
+    entities, so the API routes answer from memory instead of asking Home
+    Assistant on every poll from every tablet.
+    Service calls go over the same connection. The connection is re-opened
+    with exponential backoff; until it is up, both fall back to the REST API.
+    Listeners are told which entity changed, and screens are told over the
+    event stream whether the connection is up (if not, they poll).
+    """
+
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._send_lock = threading.Lock()
+        self._states: Dict[str, Dict] = {}
+        self._entities: set = set()
+        self._listeners: List[Callable[[str], None]] = []
+        self._pending: Dict[int, Callable[[Dict], None]] = {}
+        self._next_id = 1
+        self._subscription: Optional[int] = None
+        self._ws = None
+        self._connected = threading.Event()
+        self._backoff = HA_RECONNECT_MIN_SECONDS
+
+    def start(self, entities: List[str]) -> None:
+        self._entities = {entity for entity in entities if entity}
+        if not self._entities:
+            return
+        if not HAS_WEBSOCKET or not os.environ.get('SUPERVISOR_TOKEN'):
+            logger.info("Home Assistant WebSocket unavailable, using the REST API")
+            return
+        threading.Thread(target=self._run, daemon=True, name='home-assistant').start()
+
+    @property
+    def connected(self) -> bool:
+        return self._connected.is_set()
+
+    def add_listener(self, listener: Callable[[str], None]) -> None:
+        self._listeners.append(listener)
+
+    def _notify(self, entity_id: str) -> None:
+        for listener in self._listeners:
+            try:
+                listener(entity_id)
+            except Exception as e:
+                logger.error(f"Error handling change of {entity_id}: {e}")
+
+    def get_state(self, entity_id: str) -> Optional[Dict]:
+        """The entity's state object, or None if it doesn't exist."""
+        if self._connected.is_set() and entity_id in self._entities:
+            with self._lock:
+                return self._states.get(entity_id)
+        try:
+            return ha_rest_request(f'states/{entity_id}')
+        except Exception as e:
+            logger.error(f"Error fetching state of {entity_id}: {e}")
+            return None
+
+    def call_service(self, domain: str, service: str, data: Dict) -> bool:
+        if not self._connected.is_set():
+            try:
+                ha_rest_request(f'services/{domain}/{service}', data)
+                return True
+            except Exception as e:
+                logger.error(f"Error calling {domain}/{service}: {e}")
+                return False
+
+        done = threading.Event()
+        reply: Dict[str, Any] = {}
+
+        def on_result(message: Dict) -> None:
+            reply.update(message)
+            done.set()
+
+        try:
+            message_id = self._send({
+                'type': 'call_service',
+                'domain': domain,
+                'service': service,
+                'service_data': data
+            }, on_result)
+        except Exception as e:
+            logger.error(f"Error calling {domain}/{service}: {e}")
+            return False
+        if not done.wait(HA_CALL_TIMEOUT_SECONDS):
+            with self._lock:
+                self._pending.pop(message_id, None)
+            logger.error(f"Timed out calling {domain}/{service}")
+            return False
+        if not reply.get('success'):
+            logger.error(f"Error calling {domain}/{service}: {reply.get('error', {}).get('message')}")
+            return False
+        return True
+
+    def _send(self, message: Dict, on_result: Optional[Callable[[Dict], None]] = None) -> int:
+        with self._send_lock:
+            ws = self._ws
+            if ws is None:
+                raise HomeAssistantError("not connected")
+            message_id = self._next_id
+            self._next_id += 1
+            if on_result is not None:
+                with self._lock:
+                    self._pending[message_id] = on_result
+            ws.send(json.dumps({'id': message_id, **message}))
+        return message_id
+
+    def _run(self) -> None:
+        while True:
+            try:
+                self._session()
+            except Exception as e:
+                logger.warning(
+                    f"Home Assistant connection lost ({e}), "
+                    f"reconnecting in {self._backoff}s"
+                )
+            finally:
+                self._disconnect()
+            time.sleep(self._backoff * random.uniform(1, 1.5))
+            self._backoff = min(self._backoff * 2, HA_RECONNECT_MAX_SECONDS)
+
+    def _session(self) -> None:
+        ws = websocket.create_connection(HA_WEBSOCKET_URL, timeout=HA_PING_SECONDS)
+        with self._send_lock:
+            self._ws = ws
+
+        json.loads(ws.recv())  # auth_required
+        ws.send(json.dumps({'type': 'auth', 'access_token': os.environ.get('SUPERVISOR_TOKEN', '')}))
+        reply = json.loads(ws.recv())
+        if reply.get('type') != 'auth_ok':
+            raise HomeAssistantError(f"authentication failed: {reply.get('message', reply.get('type'))}")
+
+        # The first event of the subscription carries the current states
+        self._subscription = self._send({
+            'type': 'subscribe_entities',
+            'entity_ids': sorted(self._entities)
+        }, self._on_subscribed)
+
+        last_message = time.monotonic()
+        while True:
+            try:
+                raw = ws.recv()
+            except websocket.WebSocketTimeoutException:
+                # Quiet connection: make sure Home Assistant is still there
+                if time.monotonic() - last_message > HA_PING_SECONDS * 2:
+                    raise HomeAssistantError("no reply to ping")
+                self._send({'type': 'ping'})
+                continue
+            last_message = time.monotonic()
+            self._handle(json.loads(raw))
+
+    def _handle(self, message: Dict) -> None:
+        if message.get('type') == 'event':
+            if message.get('id') == self._subscription:
+                self._on_entities(message.get('event') or {})
+            return
+        if message.get('type') == 'result':
+            with self._lock:
+                on_result = self._pending.pop(message.get('id'), None)
+            if on_result is not None:
+                on_result(message)
+
+    def _on_subscribed(self, message: Dict) -> None:
+        if not message.get('success'):
+            logger.error(f"Cannot subscribe to Home Assistant entities: {message.get('error')}")
+
+    def _on_entities(self, event: Dict) -> None:
+        """
+        Apply a `subscribe_entities` event: `a` adds entities, `c` holds
+        `+`/`-` diffs against the last state and `r` lists removed entities.
+        """
+        initial = not self._connected.is_set()
+        changed = set()
+        with self._lock:
+            states = {} if initial else self._states
+            for entity_id, compressed in (event.get('a') or {}).items():
+                states[entity_id] = _expand_state(entity_id, compressed)
+                changed.add(entity_id)
+            for entity_id, diff in (event.get('c') or {}).items():
+                if entity_id in states:
+                    states[entity_id] = _apply_state_diff(states[entity_id], diff)
+                    changed.add(entity_id)
+            for entity_id in event.get('r') or []:
+                states.pop(entity_id, None)
+                changed.add(entity_id)
+            self._states = states
+
+        if initial:
+            for entity_id in self._entities - states.keys():
+                logger.warning(f"Entity {entity_id} not found in Home Assistant")
+            self._connected.set()
+            self._backoff = HA_RECONNECT_MIN_SECONDS
+            event_bus.publish('home_assistant', {'connected': True})
+            logger.info(f"Connected to Home Assistant, tracking {len(self._entities)} entities")
+            # Changes may have been missed while disconnected
+            changed = self._entities
+        for entity_id in changed:
+            self._notify(entity_id)
+
+    def _disconnect(self) -> None:
+        if self._connected.is_set():
+            self._connected.clear()
+            event_bus.publish('home_assistant', {'connected': False})
+        with self._send_lock:
+            ws, self._ws = self._ws, None
+            self._subscription = None
+        if ws is not None:
+            try:
+                ws.close()
+            except Exception:
+                pass
+        # Anyone waiting on a reply won't get one now
+        with self._lock:
+            pending, self._pending = self._pending, {}
+        for on_result in pending.values():
+            on_result({'success': False, 'error': {'message': 'connection lost'}})
+
+
+home_assistant = HomeAssistantClient()
+
+
+# ============================================================================
+# PRESENCE
+# ============================================================================
+
+# States that mean someone is there: binary sensors are 'on', person and
+# device_tracker entities are 'home'
+PRESENCE_ON_STATES = {'on', 'home'}
+PRESENCE_POLL_SECONDS = 30
+
+
+class PresenceMonitor:
+    """
+    Follows the presence and motion entities. While either is on the room is
+    occupied: screens hold off the screensaver and wake up. Once both have
+    been off for sleep_after_minutes, screens go to sleep. Changes are pushed
+    to screens as 'presence' events.
+    """
+
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._entities: List[str] = []
+        self._sleep_after = 0
+        self._occupied: Optional[bool] = None
+        self._since: Optional[str] = None
+        self._sleeping = False
+        self._timer: Optional[threading.Timer] = None
+
+    @property
+    def entities(self) -> List[str]:
+        return self._entities
+
+    def start(self, config: Dict[str, Any]) -> None:
+        self._entities = [
+            entity for entity in (config.get('presence_entity', ''), config.get('motion_entity', ''))
+            if entity
+        ]
+        if not self._entities:
+            return
+        self._sleep_after = int(config.get('sleep_after_minutes', 30)) * 60
+        home_assistant.add_listener(self._on_change)
+        threading.Thread(target=self._run, daemon=True, name='presence').start()
+
+    def status(self) -> Optional[Dict[str, Any]]:
+        """{occupied, since, sleep}, or None without presence entities or a known state."""
+        with self._lock:
+            if not self._entities or self._occupied is None:
+                return None
+            return {'occupied': self._occupied, 'since': self._since, 'sleep': self._sleeping}
+
+    def _run(self) -> None:
+        # State changes arrive over the WebSocket; poll only without it
+        while True:
+            if not home_assistant.connected:
+                self._update()
+            time.sleep(PRESENCE_POLL_SECONDS)
+
+    def _on_change(self, entity_id: str) -> None:
+        if entity_id in self._entities:
+            self._update()
+
+    def _update(self) -> None:
+        states = [home_assistant.get_state(entity) for entity in self._entities]
+        if all(state is None for state in states):
+            return
+        occupied = any(state and state.get('state') in PRESENCE_ON_STATES for state in states)
+
+        with self._lock:
+            if occupied == self._occupied:
+                return
+            self._occupied = occupied
+            self._since = datetime.now().isoformat(timespec='seconds')
+            self._sleeping = False
+            if self._timer is not None:
+                self._timer.cancel()
+                self._timer = None
+            if not occupied and self._sleep_after:
+                self._timer = threading.Timer(self._sleep_after, self._sleep)
+                self._timer.daemon = True
+                self._timer.start()
+        logger.info(f"Room {'occupied' if occupied else 'empty'}")
+        event_bus.publish('presence', self.status())
+
+    def _sleep(self) -> None:
+        with self._lock:
+            if self._occupied is not False:
+                return
+            self._sleeping = True
+        logger.info("Room empty, putting screens to sleep")
+        event_bus.publish('presence', self.status())
+
+
+presence_monitor = PresenceMonitor()
+
+
+# ============================================================================
+# SCREENS
+# ============================================================================
+
+# Commands Home Assistant can send through MQTT; the remote control API adds
+# commands that carry an album or photo
+SCREEN_COMMANDS = ('start', 'stop', 'next', 'previous')
+REMOTE_COMMANDS = SCREEN_COMMANDS + ('album', 'photo')
+COMMAND_ACK_SECONDS = 5
+COMMAND_MAX_WAIT_SECONDS = 30
+SCREEN_PHOTO_FIELDS = ('url', 'album', 'caption', 'date', 'location')
+
+
+SCREEN_ID_MAX_LENGTH = 64
+SCREENS_FILE = Path("/data/screens.json")
+SCREENS_VERSION = 1
+# Named screens that stay away this long are removed from Home Assistant
+SCREEN_FORGET_DAYS = 30
+
+
+def valid_screen_id(screen_id: str) -> bool:
+    return 0 < len(screen_id) <= SCREEN_ID_MAX_LENGTH and screen_id.isprintable()
+
+
+def screen_slug(screen_id: str) -> str:
+    """The screen id as used in MQTT topics and Home Assistant unique ids."""
+    return re.sub(r'[^a-z0-9_]+', '_', screen_id.lower()).strip('_') or 'default'
+
+
+class ScreenRegistry:
+    """
+    The screens (browsers showing the screensaver) the server knows about.
+    A screen is online while it has an /api/events stream open; it reports
+    whether the screensaver is running and which photo is shown. Screens
+    named with `?screen=` are remembered across restarts until they have been
+    away for SCREEN_FORGET_DAYS; unnamed ones (a random id per browser) are
+    dropped when their stream closes. Listeners are told which screen changed,
+    with 'forgotten' set when it is dropped.
+    """
+
+    def __init__(self, screens_file: Path):
+        self.screens_file = screens_file
+        self._lock = threading.Lock()
+        self._screens: Dict[str, Dict[str, Any]] = {}
+        self._listeners: List[Callable[[Dict[str, Any]], None]] = []
+
+    def load(self) -> None:
+        """Restore the named screens."""
+        try:
+            if self.screens_file.exists():
+                with open(self.screens_file, 'r') as f:
+                    data = json.load(f)
+                if data.get('version') == SCREENS_VERSION:
+                    with self._lock:
+                        for saved in data.get('screens', []):
+                            self._screens[saved['id']] = {
+                                **self._new_screen(saved['id'], saved['slug']),
+                                'named': True,
+                                'seen': saved.get('seen', time.time()),
+                            }
+        except Exception as e:
+            logger.warning(f"Ignoring unreadable screens file: {e}")
+
+    def _save(self) -> None:
+        with self._lock:
+            data = {
+                'version': SCREENS_VERSION,
+                'screens': [
+                    {'id': screen['id'], 'slug': screen['slug'], 'seen': screen['seen']}
+                    for screen in self._screens.values() if screen['named']
+                ]
+            }
+        try:
+            self.screens_file.parent.mkdir(parents=True, exist_ok=True)
+            tmp_file = self.screens_file.with_suffix('.tmp')
+            with open(tmp_file, 'w') as f:
+                json.dump(data, f, indent=2)
+            os.replace(tmp_file, self.screens_file)
+        except Exception as e:
+            logger.warning(f"Failed to save screens: {e}")
+
+    def add_listener(self, listener: Callable[[Dict[str, Any]], None]) -> None:
+        self._listeners.append(listener)
+
+    @staticmethod
+    def _new_screen(screen_id: str, slug: str) -> Dict[str, Any]:
+        return {
+            'id': screen_id,
+            'slug': slug,
+            'named': False,
+            'streams': 0,
+            'active': False,
+            'photo': None,
+            'updated': None,
+            'seen': time.time(),
+        }
+
+    def _screen(self, screen_id: str) -> Dict[str, Any]:
+        screen = self._screens.get(screen_id)
+        if screen is None:
+            # "Living Room" and "living-room" must not share MQTT entities
+            base = slug = screen_slug(screen_id)
+            taken = {other['slug'] for other in self._screens.values()}
+            suffix = 2
+            while slug in taken:
+                slug = f'{base}_{suffix}'
+                suffix += 1
+            screen = self._screens[screen_id] = self._new_screen(screen_id, slug)
+        return screen
+
+    def connect(self, screen_id: str, named: bool) -> None:
+        with self._lock:
+            screen = self._screen(screen_id)
+            screen['streams'] += 1
+            screen['seen'] = time.time()
+            newly_named = named and not screen['named']
+            screen['named'] = screen['named'] or named
+            snapshot = dict(screen)
+        if newly_named:
+            self._save()
+        self._notify(snapshot)
+        self.forget_stale()
+
+    def disconnect(self, screen_id: str) -> None:
+        with self._lock:
+            screen = self._screen(screen_id)
+            screen['streams'] = max(0, screen['streams'] - 1)
+            screen['seen'] = time.time()
+            if screen['streams'] == 0:
+                screen['active'] = False
+                if not screen['named']:
+                    del self._screens[screen_id]
+            snapshot = dict(screen)
+        if snapshot['named']:
+            self._save()
+        self._notify(snapshot)
+
+    def report(self, screen_id: str, active: bool,
+               photo: Optional[Dict[str, Any]]) -> Optional[Dict[str, Any]]:
+        """Update a connected screen; None if the screen has no stream open."""
+        if photo:
+            photo = {key: photo[key] for key in SCREEN_PHOTO_FIELDS if photo.get(key)}
+        with self._lock:
+            screen = self._screens.get(screen_id)
+            if screen is None or not screen['streams']:
+                return None
+            screen['active'] = active
+            screen['photo'] = photo or None
+            screen['updated'] = datetime.now().isoformat(timespec='seconds')
+            snapshot = dict(screen)
+        self._notify(snapshot)
+        return snapshot
+
+    def forget(self, screen_id: str) -> bool:
+        """Drop a screen that is offline; False if it is unknown or online."""
+        with self._lock:
+            screen = self._screens.get(screen_id)
+            if screen is None or screen['streams']:
+                return False
+            del self._screens[screen_id]
+        self._save()
+        self._notify({**screen, 'forgotten': True})
+        return True
+
+    def forget_stale(self) -> None:
+        cutoff = time.time() - SCREEN_FORGET_DAYS * 86400
+        with self._lock:
+            stale = [
+                screen['id'] for screen in self._screens.values()
+                if not screen['streams'] and screen['seen'] < cutoff
+            ]
+        for screen_id in stale:
+            if self.forget(screen_id):
+                logger.info(f"Forgot screen {screen_id}, not seen for {SCREEN_FORGET_DAYS} days")
+
+    def get(self, screen_id: str) -> Optional[Dict[str, Any]]:
+        with self._lock:
+            screen = self._screens.get(screen_id)
+            return dict(screen) if screen else None
+
+    def all(self) -> List[Dict[str, Any]]:
+        with self._lock:
+            return [dict(screen) for screen in self._screens.values()]
+
+    def find_slug(self, slug: str) -> Optional[Dict[str, Any]]:
+        with self._lock:
+            for screen in self._screens.values():
+                if screen['slug'] == slug:
+                    return dict(screen)
+        return None
+
+    def _notify(self, screen: Dict[str, Any]) -> None:
+        for listener in self._listeners:
+            try:
+                listener(screen)
+            except Exception as e:
+                logger.error(f"Error handling change of screen {screen['id']}: {e}")
+
+
+screens = ScreenRegistry(SCREENS_FILE)
+
+
+class ScreenCommands:
+    """
+    Delivers remote control commands to screens over their event streams.
+    Tracked commands carry an id that screens acknowledge once they have
+    carried the command out (or failed to), so the caller can wait for them.
+    """
+
+    def __init__(self):
+        self._cond = threading.Condition()
+        self._next_id = 1
+        self._acks: Dict[int, Dict[str, Dict[str, Any]]] = {}
+
+    def send(self, command: str, screen_id: Optional[str] = None,
+             args: Optional[Dict[str, Any]] = None, track: bool = True) -> Optional[int]:
+        """Send a command to one screen, or every screen when screen_id is None."""
+        command_id = None
+        if track:
+            with self._cond:
+                command_id = self._next_id
+                self._next_id += 1
+                self._acks[command_id] = {}
+        event_bus.publish('command', {
+            'id': command_id, 'screen': screen_id, 'command': command, **(args or {})
+        })
+        return command_id
+
+    def acknowledge(self, command_id: int, screen_id: str, error: Optional[str]) -> bool:
+        with self._cond:
+            acks = self._acks.get(command_id)
+            if acks is None:
+                return False
+            acks[screen_id] = {'ok': error is None, 'error': error}
+            self._cond.notify_all()
+        return True
+
+    def wait(self, command_id: int, screen_ids: set, timeout: float) -> Dict[str, Dict[str, Any]]:
+        """Wait for the screens to acknowledge, then forget the command."""
+        deadline = time.monotonic() + timeout
+        with self._cond:
+            while not screen_ids <= self._acks[command_id].keys():
+                remaining = deadline - time.monotonic()
+                if remaining <= 0:
+                    break
+                self._cond.wait(remaining)
+            return self._acks.pop(command_id)
+
+
+screen_commands = ScreenCommands()
+
+
+# ============================================================================
+# MQTT DISCOVERY
+# ============================================================================
+
+MQTT_TOPIC_PREFIX = 'ha_screensaver'
+MQTT_DISCOVERY_PREFIX = 'homeassistant'
+MQTT_RECONNECT_MAX_SECONDS = 60
+
+
+class MqttBridge:
+    """
+    Publishes every named screen to Home Assistant through MQTT discovery, as a
+    device with a "screensaver running" binary sensor, a current photo
+    sensor, a switch to start and stop the screensaver and next and previous
+    photo buttons. Commands from Home Assistant go to the screen over its event
+    stream. Screens show as unavailable while their stream is closed, and
+    all of them while the add-on is down (through the MQTT last will).
+    Forgotten screens are removed by clearing their retained topics.
+    """
+
+    def __init__(self, registry: ScreenRegistry):
+        self.registry = registry
+        self._client = None
+        self._lock = threading.Lock()
+        self._discovered: set = set()
+
+    def start(self, config: Dict[str, Any]) -> None:
+        host = config.get('mqtt_host', '')
+        if not config.get('mqtt_discovery', True) or not host:
+            return
+        if not HAS_MQTT:
+            logger.warning("paho-mqtt not installed, screens are not published to MQTT")
+            return
+
+        client = mqtt.Client(mqtt.CallbackAPIVersion.VERSION2, client_id='ha_screensaver')
+        if config.get('mqtt_username'):
+            client.username_pw_set(config['mqtt_username'], config.get('mqtt_password') or None)
+        client.will_set(f'{MQTT_TOPIC_PREFIX}/status', 'offline', retain=True)
+        client.on_connect = self._on_connect
+        client.on_message = self._on_message
+        client.reconnect_delay_set(1, MQTT_RECONNECT_MAX_SECONDS)
+        self._client = client
+        self.registry.add_listener(self._publish_screen)
+
+        port = int(config.get('mqtt_port', 1883))
+        logger.info(f"Publishing screens to MQTT broker {host}:{port}")
+        client.connect_async(host, port)
+        client.loop_start()
+
+    def _on_connect(self, client, userdata, flags, reason_code, properties) -> None:
+        if reason_code.is_failure:
+            logger.error(f"MQTT connection refused: {reason_code}")
+            return
+        logger.info("Connected to MQTT broker")
+        with self._lock:
+            self._discovered.clear()
+        client.publish(f'{MQTT_TOPIC_PREFIX}/status', 'online', retain=True)
+        client.subscribe(f'{MQTT_TOPIC_PREFIX}/+/command')
+        client.subscribe(f'{MQTT_DISCOVERY_PREFIX}/status')
+        self.registry.forget_stale()
+        self._publish_all()
+
+    def _on_message(self, client, userdata, message) -> None:
+        payload = message.payload.decode('utf-8', 'replace').strip().lower()
+        if message.topic == f'{MQTT_DISCOVERY_PREFIX}/status':
+            # Home Assistant restarted: discovery configs must be sent again
+            if payload == 'online':
+                with self._lock:
+                    self._discovered.clear()
+                self._publish_all()
+            return
+
+        slug = message.topic.split('/')[1]
+        screen = self.registry.find_slug(slug)
+        if screen is None or not screen['named'] or payload not in SCREEN_COMMANDS:
+            logger.warning(f"Ignoring MQTT command '{payload}' for screen {slug}")
+            return
+        screen_commands.send(payload, screen['id'], track=False)
+
+    def _publish_all(self) -> None:
+        for screen in self.registry.all():
+            self._publish_screen(screen)
+
+    def _publish_screen(self, screen: Dict[str, Any]) -> None:
+        client = self._client
+        if client is None or not client.is_connected():
+            return
+        if screen.get('forgotten'):
+            self._remove_screen(screen)
+            return
+        if not screen['named']:
+            return
+        slug = screen['slug']
+        base = f'{MQTT_TOPIC_PREFIX}/{slug}'
+
+        with self._lock:
+            first = slug not in self._discovered
+            self._discovered.add(slug)
+        if first:
+            for component, object_id, entity in self._entities(screen):
+                topic = f'{MQTT_DISCOVERY_PREFIX}/{component}/{MQTT_TOPIC_PREFIX}_{slug}/{object_id}/config'
+                client.publish(topic, json.dumps(entity), retain=True)
+
+        photo = screen.get('photo') or {}
+        state = {
+            'active': 'ON' if screen['active'] else 'OFF',
+            # Entity states are limited to 255 characters
+            'photo': (_photo_rel_path(photo) if photo.get('url', '').startswith('/photos/') else '')[:255],
+            'album': photo.get('album', ''),
+            'caption': photo.get('caption', ''),
+            'date': photo.get('date', ''),
+            'location': photo.get('location', ''),
+            'updated': screen.get('updated'),
+        }
+        client.publish(f'{base}/state', json.dumps(state), retain=True)
+        client.publish(f'{base}/availability', 'online' if screen['streams'] else 'offline', retain=True)
+
+    def _remove_screen(self, screen: Dict[str, Any]) -> None:
+        """An empty retained config removes the entity from Home Assistant."""
+        client = self._client
+        slug = screen['slug']
+        for component, object_id, _ in self._entities(screen):
+            topic = f'{MQTT_DISCOVERY_PREFIX}/{component}/{MQTT_TOPIC_PREFIX}_{slug}/{object_id}/config'
+            client.publish(topic, '', retain=True)
+        client.publish(f'{MQTT_TOPIC_PREFIX}/{slug}/state', '', retain=True)
+        client.publish(f'{MQTT_TOPIC_PREFIX}/{slug}/availability', '', retain=True)
+        with self._lock:
+            self._discovered.discard(slug)
+        logger.info(f"Removed screen {screen['id']} from Home Assistant")
+
+    def _entities(self, screen: Dict[str, Any]) -> List[Tuple[str, str, Dict[str, Any]]]:
+        slug = screen['slug']
+        base = f'{MQTT_TOPIC_PREFIX}/{slug}'
+        common = {
+            'device': {
+                'identifiers': [f'{MQTT_TOPIC_PREFIX}_{slug}'],
+                'name': f"Screensaver {screen['id']}",
+                'model': 'Home Assistant Screensaver',
+            },
+            'availability': [
+                {'topic': f'{MQTT_TOPIC_PREFIX}/status'},
+                {'topic': f'{base}/availability'},
+            ],
+            'availability_mode': 'all',
+        }
+        return [
+            ('binary_sensor', 'active', {
+                **common,
+                'name': 'Active',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_active',
+                'device_class': 'running',
+                'state_topic': f'{base}/state',
+                'value_template': '{{ value_json.active }}',
+            }),
+            ('sensor', 'photo', {
+                **common,
+                'name': 'Photo',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_photo',
+                'icon': 'mdi:image',
+                'state_topic': f'{base}/state',
+                'value_template': '{{ value_json.photo }}',
+                'json_attributes_topic': f'{base}/state',
+            }),
+            ('sensor', 'album', {
+                **common,
+                'name': 'Album',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_album',
+                'icon': 'mdi:folder-image',
+                'state_topic': f'{base}/state',
+                'value_template': '{{ value_json.album }}',
+            }),
+            ('switch', 'screensaver', {
+                **common,
+                'name': 'Screensaver',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_screensaver',
+                'icon': 'mdi:monitor-shimmer',
+                'state_topic': f'{base}/state',
+                'value_template': '{{ value_json.active }}',
+                'command_topic': f'{base}/command',
+                'payload_on': 'start',
+                'payload_off': 'stop',
+                'state_on': 'ON',
+                'state_off': 'OFF',
+            }),
+            ('button', 'next', {
+                **common,
+                'name': 'Next photo',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_next',
+                'icon': 'mdi:skip-next',
+                'command_topic': f'{base}/command',
+                'payload_press': 'next',
+            }),
+            ('button', 'previous', {
+                **common,
+                'name': 'Previous photo',
+                'unique_id': f'{MQTT_TOPIC_PREFIX}_{slug}_previous',
+                'icon': 'mdi:skip-previous',
+                'command_topic': f'{base}/command',
+                'payload_press': 'previous',
+            }),
+        ]
+
+
+mqtt_bridge = MqttBridge(screens)
+
+
+# ============================================================================
+# PHOTO INDEX
+# ============================================================================
+
+INDEX_FILE = Path("/data/photo_index.json")
+INDEX_VERSION = 6
+
+IMAGE_EXTENSIONS = {'.jpg', '.jpeg', '.png', '.gif', '.webp'}
+
+# Formats most kiosk browsers can't display; converted when served
+TRANSCODE_EXTENSIONS = set()
+if HAS_HEIF:
+    TRANSCODE_EXTENSIONS |= {'.heic', '.heif'}
+if HAS_AVIF:
+    TRANSCODE_EXTENSIONS |= {'.avif'}
+IMAGE_EXTENSIONS |= TRANSCODE_EXTENSIONS
+
+VIDEO_EXTENSIONS = {'.mp4', '.mov', '.webm'}
+
+
+class PhotoIndex:
+    """
+    Persistent index of the photos folder.
+
+    Every entry records the file's size and mtime next to its EXIF fields and
+    resolved location, so a rescan only opens files that are new or changed.
+    The index is served from memory and written to disk after each scan that
+    modified it, which keeps it warm across add-on restarts.
+    """
+
+    def __init__(self, index_file: Path):
+        self.index_file = index_file
+        self._lock = threading.Lock()
+        self._scan_lock = threading.Lock()
+        self._loaded = False
+        self._folder: Optional[str] = None
+        self._max_depth: Optional[int] = None
+        self._entries: Dict[str, Dict[str, Any]] = {}
+        self._last_scan = 0.0
+        # Bumped whenever files are added, changed or removed
+        self.generation = 0
+
+    def _load(self) -> None:
+        """Load the on-disk index once per process."""
+        if self._loaded:
+            return
+        self._loaded = True
+        try:
+            if self.index_file.exists():
+                with open(self.index_file, 'r') as f:
+                    data = json.load(f)
+                if data.get('version') == INDEX_VERSION:
+                    self._folder = data.get('folder')
+                    self._entries = data.get('photos', {})
+                    logger.info(f"Loaded photo index with {len(self._entries)} entries")
+        except Exception as e:
+            logger.warning(f"Ignoring unreadable photo index: {e}")
+
+    def _save(self) -> None:
+        """Write the index atomically so a crash never leaves a torn file."""
+        with self._lock:
+            data = {
+                'version': INDEX_VERSION,
+                'folder': self._folder,
+                'photos': self._entries
+            }
+        try:
+            self.index_file.parent.mkdir(parents=True, exist_ok=True)
+            tmp_file = self.index_file.with_suffix('.tmp')
+            with open(tmp_file, 'w') as f:
+                json.dump(data, f)
+            os.replace(tmp_file, self.index_file)
+        except Exception as e:
+            logger.warning(f"Failed to save photo index: {e}")
+
+    def photos(self, folder_path: str, max_depth: int) -> List[Dict[str, Any]]:
+        """
+        Return the indexed photos for the folder. Only the first call (or a
+        change of folder) scans synchronously; afterwards the PhotoWatcher keeps
+        the in-memory index current.
+        """
+        if (self._folder != folder_path or self._max_depth != max_depth
+                or not self._last_scan):
+            self.scan(folder_path, max_depth)
+        return self.current()
+
+    def current(self) -> List[Dict[str, Any]]:
+        """Return the photos as currently indexed, without scanning."""
+        with self._lock:
+            entries = sorted(self._entries.items())
+        return [_photo_response(rel_path, entry) for rel_path, entry in entries]
+
+    def perceptual_hashes(self) -> Dict[str, int]:
+        """Return the perceptual hash of every indexed image that has one."""
+        with self._lock:
+            return {
+                rel_path: int(entry['exif']['phash'], 16)
+                for rel_path, entry in self._entries.items()
+                if entry['exif'].get('phash')
+            }
+
+    def get(self, rel_path: str) -> Optional[Dict[str, Any]]:
+        """Return the index entry for a photo, if it has been indexed."""
+        with self._lock:
+            return self._entries.get(rel_path)
+
+    def scan(self, folder_path: str, max_depth: int) -> None:
+        """
+        Bring the index up to date with the folder, re-reading only changed
+        files, and publish a 'photos' event describing what changed.
+        """
+        with self._scan_lock:
+            self._load()
+            with self._lock:
+                previous = dict(self._entries) if self._folder == folder_path else {}
+
+            entries = self._scan_folder(folder_path, max_depth, previous)
+            self._last_scan = time.time()
+            if entries is None:
+                return
+
+            changed = [rel for rel, entry in entries.items() if previous.get(rel) is not entry]
+            removed = set(previous) - set(entries)
+            geocoded = self._resolve_locations(entries)
+
+            with self._lock:
+                self._folder = folder_path
+                self._max_depth = max_depth
+                self._entries = entries
+                if changed or removed:
+                    self.generation += 1
+
+            if changed or removed or geocoded:
+                self._save()
+
+            if changed or removed:
+                logger.info(
+                    f"Indexed {len(entries)} photos in {folder_path} "
+                    f"({len(changed)} new or changed, {len(removed)} removed)"
+                )
+            if changed or removed or geocoded:
+                publish_photo_changes(
+                    [_photo_response(rel, entries[rel]) for rel in sorted(set(changed) | set(geocoded))],
+                    [_photo_url(rel) for rel in sorted(removed)]
+                )
+
+    def _scan_folder(self, folder_path: str, max_depth: int,
+                     previous: Dict[str, Dict[str, Any]]) -> Optional[Dict[str, Dict[str, Any]]]:
+        """
+        Walk the folder and build the new entry map. Unchanged files reuse their
+        previous entry object. Returns None if the folder could not be read.
+        """
+        folder = Path(folder_path)
+
+        if not folder.exists():
+            logger.warning(f"Photos folder does not exist: {folder_path}")
+            return {}
+
+        if not folder.is_dir():
+            logger.warning(f"Photos path is not a directory: {folder_path}")
+            return {}
+
+        entries = {}
+        # A trash folder inside the photos folder must not be played again
+        trash = trash_root(folder_path, load_config().get('trash_folder', ''))
+        try:
+            for file_path, stat in _walk_photo_files(folder, max_depth, skip=trash):
+                rel_path = file_path.relative_to(folder).as_posix()
+                sidecar = xmp_sidecar(file_path)
+                sidecar_mtime = sidecar.stat().st_mtime if sidecar else None
+                old = previous.get(rel_path)
+                if (old and old.get('size') == stat.st_size and old.get('mtime') == stat.st_mtime
+                        and old.get('sidecar_mtime') == sidecar_mtime):
+                    entries[rel_path] = old
+                    continue
+
+                if _is_video(rel_path):
+                    metadata = extract_video_metadata(str(file_path))
+                else:
+                    metadata = extract_exif(str(file_path))
+                # Sidecars hold the latest edits from Lightroom/digiKam, so they win
+                if sidecar:
+                    metadata.update(read_xmp_sidecar(sidecar))
+                entries[rel_path] = {
+                    'size': stat.st_size,
+                    'mtime': stat.st_mtime,
+                    'sidecar_mtime': sidecar_mtime,
+                    'exif': metadata
+                }
+            return entries
+
+        except PermissionError as e:
+            logger.error(f"Permission denied reading folder {folder_path}: {e}")
+            return None
+        except Exception as e:
+            logger.error(f"Error scanning photos folder: {e}")
+            return None
+
+    @staticmethod
+    def _resolve_locations(entries: Dict[str, Dict[str, Any]]) -> List[str]:
+        """
+        Fill in place names for entries whose GPS coordinates have not been
+        resolved yet, were resolved with different geocoding settings, or
+        came back empty longer than geocoding_retry_hours ago. Names already
+        in the geocache are applied now; the rest are queued on the geocoding
+        worker and arrive later through apply_locations(). Returns the paths
+        of entries that were updated.
+        """
+        signature = geocoding_worker.signature
+        expired = time.time() - geocoding_worker.negative_ttl
+        updated = []
+        for rel_path, entry in entries.items():
+            exif = entry['exif']
+            if 'lat' not in exif:
+                continue
+            if entry.get('geocoder') == signature and (
+                    entry.get('location') or entry.get('geocoded', 0) > expired):
+                continue
+            resolved, name = geocoding_worker.lookup(exif['lat'], exif['lng'])
+            if resolved:
+                changed = entry.get('location') != name
+                entry['location'] = name
+                entry['geocoder'] = signature
+                entry['geocoded'] = time.time()
+                if changed:
+                    updated.append(rel_path)
+        return updated
+
+    def apply_locations(self, resolved: Dict[str, Optional[str]]) -> None:
+        """Apply finished geocoding lookups (keyed by geocache key) and push them to screens."""
+        signature = geocoding_worker.signature
+        with self._scan_lock:
+            updated = []
+            with self._lock:
+                for rel_path, entry in self._entries.items():
+                    exif = entry['exif']
+                    if 'lat' not in exif:
+                        continue
+                    key = geocoding_worker.key(exif['lat'], exif['lng'])
+                    if key in resolved:
+                        entry['location'] = resolved[key]
+                        entry['geocoder'] = signature
+                        entry['geocoded'] = time.time()
+                        updated.append(rel_path)
+
+            if updated:
+                self._save()
+                publish_photo_changes(
+                    [_photo_response(rel, self._entries[rel]) for rel in updated], []
+                )
+
+    def location_keys(self, unresolved_only: bool = False) -> set:
+        """Geocache keys of indexed photos with coordinates (optionally only unresolved ones)."""
+        self._load()
+        with self._lock:
+            return {
+                geocoding_worker.key(entry['exif']['lat'], entry['exif']['lng'])
+                for entry in self._entries.values()
+                if 'lat' in entry['exif'] and not (unresolved_only and entry.get('location'))
+            }
+
+    def refresh_locations(self, keys: Optional[set] = None) -> int:
+        """
+        Re-run location resolution over the index, e.g. after negative
+        results expire or the geocache was purged. With keys, only entries
+        with those geocache keys are forced to resolve again. Returns the
+        number of photos whose location is pending or changed.
+        """
+        with self._scan_lock:
+            self._load()
+            with self._lock:
+                entries = self._entries
+                if keys is not None:
+                    for entry in entries.values():
+                        exif = entry['exif']
+                        if 'lat' in exif and geocoding_worker.key(exif['lat'], exif['lng']) in keys:
+                            entry.pop('geocoder', None)
+                updated = self._resolve_locations(entries)
+
+            forced = sum(1 for e in entries.values() if 'lat' in e['exif'] and 'geocoder' not in e)
+            if updated or forced:
+                self._save()
+            if updated:
+                publish_photo_changes(
+                    [_photo_response(rel, entries[rel]) for rel in updated], []
+                )
+            return len(updated) + forced
+
+
+def _is_video(name: str) -> bool:
+    return Path(name).suffix.lower() in VIDEO_EXTENSIONS
+
+
+def _is_media_file(name: str) -> bool:
+    suffix = Path(name).suffix.lower()
+    return suffix in IMAGE_EXTENSIONS or suffix in VIDEO_EXTENSIONS
+
+
+def _walk_photo_files(folder: Path, max_depth: int,
+                      skip: Optional[Path] = None) -> Iterator[Tuple[Path, os.stat_result]]:
+    """
+    Recursively yield (path, stat) for every image and video below the folder.
+
+    Symlinked directories are followed, but each directory is visited at most
+    once (keyed by device and inode) so symlink loops cannot recurse forever.
+    Hidden files and folders, and the skip folder, are skipped. Unreadable
+    subfolders are logged and skipped; an unreadable top-level folder raises
+    PermissionError.
+    """
+    skip_path = os.path.normpath(skip) if skip else None
+    visited = set()
+    stack = [(folder, 0)]
+    while stack:
+        directory, depth = stack.pop()
+        try:
+            dir_stat = directory.stat()
+            if (dir_stat.st_dev, dir_stat.st_ino) in visited:
+                logger.debug(f"Skipping already visited folder: {directory}")
+                continue
+            visited.add((dir_stat.st_dev, dir_stat.st_ino))
+            children = list(os.scandir(directory))
+        except PermissionError:
+            if directory == folder:
+                raise
+            logger.warning(f"Permission denied reading folder {directory}")
+            continue
+        except OSError as e:
+            logger.warning(f"Skipping unreadable folder {directory}: {e}")
+            continue
+
+        for child in children:
+            if child.name.startswith('.'):
+                continue
+            try:
+                if child.is_dir():
+                    if os.path.normpath(child.path) == skip_path:
+                        continue
+                    if depth < max_depth:
+                        stack.append((Path(child.path), depth + 1))
+                elif child.is_file() and _is_media_file(child.name):
+                    yield Path(child.path), child.stat()
+            except OSError as e:
+                # Broken symlinks and files removed mid-scan
+                logger.debug(f"Skipping {child.path}: {e}")
+
+
+def _album_name(rel_path: str) -> str:
+    """The album of a photo is the folder it lives in, relative to the photos folder."""
+    return rel_path.rpartition('/')[0]
+
+
+def _photo_url(rel_path: str) -> str:
+    return f"/photos/{urllib.parse.quote(rel_path)}"
+
+
+# Descriptive metadata passed through to /api/photos as-is
+PHOTO_DESCRIPTION_FIELDS = ('caption', 'keywords', 'rating', 'people', 'camera', 'lens')
+
+
+def focal_point(exif: Dict[str, Any]) -> Optional[Dict[str, float]]:
+    """
+    The point of a photo to keep in view when it's cropped: the middle of
+    the tagged faces when there are any, else the saliency estimate.
+    """
+    faces = exif.get('faces')
+    if faces:
+        left = min(x - w / 2 for x, _, w, _ in faces)
+        right = max(x + w / 2 for x, _, w, _ in faces)
+        top = min(y - h / 2 for _, y, _, h in faces)
+        bottom = max(y + h / 2 for _, y, _, h in faces)
+        return {"x": round((left + right) / 2, 3), "y": round((top + bottom) / 2, 3), "source": "faces"}
+    if exif.get('saliency'):
+        x, y = exif['saliency']
+        return {"x": x, "y": y, "source": "saliency"}
+    return None
+
+
+def _photo_response(rel_path: str, entry: Dict[str, Any]) -> Dict[str, Any]:
+    """Shape an index entry the way /api/photos returns it."""
+    exif = entry.get('exif', {})
+    metadata = {}
+    if 'date' in exif:
+        metadata['date'] = exif['date']
+        if 'taken' in exif:
+            metadata['taken'] = exif['taken']
+    if 'lat' in exif and 'lng' in exif:
+        metadata['location'] = entry.get('location') or _format_coords(exif['lat'], exif['lng'])
+    if 'orientation' in exif:
+        metadata['orientation'] = exif['orientation']
+    if photo_state.is_favorite(rel_path):
+        metadata['favorite'] = True
+    for field in PHOTO_DESCRIPTION_FIELDS:
+        if field in exif:
+            metadata[field] = exif[field]
+
+    if _is_video(rel_path):
+        return {
+            "url": _photo_url(rel_path),
+            "type": "video",
+            "duration": exif.get('duration'),
+            "album": _album_name(rel_path),
+            "exif": metadata
+        }
+    return {
+        "url": _photo_url(rel_path),
+        "type": "image",
+        "rendition_url": f"/renditions/{urllib.parse.quote(rel_path)}?v={int(entry.get('mtime', 0))}",
+        "width": exif.get('width'),
+        "height": exif.get('height'),
+        "focus": focal_point(exif),
+        "album": _album_name(rel_path),
+        "exif": metadata
+    }
+
+
+photo_index = PhotoIndex(INDEX_FILE)
+
+
+# ============================================================================
+# PHOTO STATE
+# ============================================================================
+
+PHOTO_STATE_FILE = Path("/data/photo_state.json")
+PHOTO_STATE_VERSION = 1
+TRASH_FOLDER = '.trash'
+
+
+class PhotoStateStore:
+    """
+    Favourite and hidden marks set from the screens, keyed by the photo's
+    path inside the photos folder. Hidden photos stay indexed but never play.
+    """
+
+    def __init__(self, state_file: Path):
+        self.state_file = state_file
+        self._lock = threading.Lock()
+        self._favorites: Optional[set] = None
+        self._hidden: set = set()
+
+    def _load(self) -> None:
+        if self._favorites is not None:
+            return
+        self._favorites = set()
+        try:
+            if self.state_file.exists():
+                with open(self.state_file, 'r') as f:
+                    data = json.load(f)
+                if data.get('version') == PHOTO_STATE_VERSION:
+                    self._favorites = set(data.get('favorites', []))
+                    self._hidden = set(data.get('hidden', []))
+        except Exception as e:
+            logger.warning(f"Ignoring unreadable photo state: {e}")
+
+    def _save(self) -> None:
+        data = {
+            'version': PHOTO_STATE_VERSION,
+            'favorites': sorted(self._favorites),
+            'hidden': sorted(self._hidden)
+        }
+        try:
+            self.state_file.parent.mkdir(parents=True, exist_ok=True)
+            tmp_file = self.state_file.with_suffix('.tmp')
+            with open(tmp_file, 'w') as f:
+                json.dump(data, f, indent=2)
+            os.replace(tmp_file, self.state_file)
+        except Exception as e:
+            logger.warning(f"Failed to save photo state: {e}")
+
+    def is_favorite(self, rel_path: str) -> bool:
+        with self._lock:
+            self._load()
+            return rel_path in self._favorites
+
+    def is_hidden(self, rel_path: str) -> bool:
+        with self._lock:
+            self._load()
+            return rel_path in self._hidden
+
+    def hidden(self) -> List[str]:
+        with self._lock:
+            self._load()
+            return sorted(self._hidden)
+
+    def set_favorite(self, rel_path: str, favorite: bool) -> None:
+        with self._lock:
+            self._load()
+            (self._favorites.add if favorite else self._favorites.discard)(rel_path)
+            self._save()
+
+    def set_hidden(self, rel_path: str, hidden: bool) -> None:
+        with self._lock:
+            self._load()
+            (self._hidden.add if hidden else self._hidden.discard)(rel_path)
+            self._save()
+
+    def forget(self, rel_path: str) -> None:
+        with self._lock:
+            self._load()
+            self._favorites.discard(rel_path)
+            self._hidden.discard(rel_path)
+            self._save()
+
+
+photo_state = PhotoStateStore(PHOTO_STATE_FILE)
+
+
+def trash_root(folder_path: str, trash_folder: str) -> Path:
+    """The trash folder; a relative one lives inside the photos folder."""
+    return Path(folder_path) / (trash_folder or TRASH_FOLDER)
+
+
+def move_to_trash(folder_path: str, rel_path: str, trash_folder: str) -> Path:
+    """
+    Move a photo (and its XMP sidecar) into the trash folder, keeping its
+    album path. The trash folder is skipped when indexing. Returns the new
+    location.
+    """
+    source = Path(folder_path) / rel_path
+    target = trash_root(folder_path, trash_folder) / rel_path
+    if target.exists():
+        target = target.with_name(f"{target.stem}-{int(time.time())}{target.suffix}")
+    target.parent.mkdir(parents=True, exist_ok=True)
+    sidecar = xmp_sidecar(source)
+    shutil.move(str(source), str(target))
+    if sidecar:
+        shutil.move(str(sidecar), str(target.parent / (target.name + '.xmp')))
+    return target
+
+
+# ============================================================================
+# PHOTO FOLDER WATCHER
+# ============================================================================
+
+WATCH_DEBOUNCE_SECONDS = 2
+WATCH_POLL_SECONDS = 60
+WATCH_EVENT_TYPES = {'created', 'deleted', 'moved', 'modified', 'closed'}
+
+
+class PhotoWatcher:
+    """
+    Keeps the photo index in sync with the photos folder.
+
+    Uses inotify (through watchdog) and rescans shortly after the last change
+    in a burst, so copying a few hundred photos triggers a single scan. When
+    watchdog is unavailable or the folder can't be watched (e.g. the inotify
+    watch limit is exhausted), or the watch stops later on, it falls back to
+    rescanning periodically.
+    """
+
+    def __init__(self, index: PhotoIndex, folder_path: str, max_depth: int):
+        self.index = index
+        self.folder_path = folder_path
+        self.max_depth = max_depth
+        self._changed = threading.Event()
+
+    def start(self) -> None:
+        threading.Thread(target=self._run, daemon=True, name='photo-watcher').start()
+
+    def _start_observer(self):
+        if not HAS_WATCHDOG:
+            logger.info(f"watchdog not installed, polling {self.folder_path} every {WATCH_POLL_SECONDS}s")
+            return None
+
+        watcher = self
+
+        class Handler(FileSystemEventHandler):
+            def on_any_event(self, event):
+                if event.event_type in WATCH_EVENT_TYPES:
+                    watcher._changed.set()
+
+        try:
+            observer = Observer()
+            observer.schedule(Handler(), self.folder_path, recursive=self.max_depth > 0)
+            observer.daemon = True
+            observer.start()
+            logger.info(f"Watching {self.folder_path} for changes")
+            return observer
+        except Exception as e:
+            logger.warning(
+                f"Cannot watch {self.folder_path} ({e}), "
+                f"polling every {WATCH_POLL_SECONDS}s instead"
+            )
+            return None
+
+    def _rescan(self) -> None:
+        try:
+            self.index.scan(self.folder_path, self.max_depth)
+        except Exception as e:
+            logger.error(f"Error rescanning photos folder: {e}")
+
+    def _run(self) -> None:
+        self._rescan()
+        observer = self._start_observer()
+
+        while True:
+            if observer is not None and not observer.is_alive():
+                # e.g. the watch limit was hit on a new folder or a mount went away
+                logger.warning(
+                    f"Stopped watching {self.folder_path}, "
+                    f"polling every {WATCH_POLL_SECONDS}s instead"
+                )
+                observer = None
+            if observer is None:
+                time.sleep(WATCH_POLL_SECONDS)
+                self._rescan()
+                continue
+
+            # Wake up now and then to make sure the observer is still running
+            if not self._changed.wait(WATCH_POLL_SECONDS):
+                continue
+            self._changed.clear()
+            # Debounce: wait until the folder has been quiet for a moment
+            while self._changed.wait(WATCH_DEBOUNCE_SECONDS):
+                self._changed.clear()
+            self._rescan()
+
+
+# ============================================================================
+# PHOTO RENDITIONS
+# ============================================================================
+
+RENDITION_CACHE_DIR = Path("/data/renditions")
+RENDITION_SIZE_STEP = 160
+RENDITION_MAX_SIDE = 3840
+RENDITION_MIMETYPES = {'jpeg': 'image/jpeg', 'webp': 'image/webp'}
+
+# Decoding a 24 MP photo takes ~100 MB, so cap concurrent resizes
+_rendition_slots = threading.BoundedSemaphore(2)
+
+
+@lru_cache(maxsize=1)
+def _srgb_profile():
+    return ImageCms.createProfile('sRGB')
+
+
+def _rendition_bucket(value: Optional[int], default: int) -> int:
+    """
+    Round a requested dimension up to the next size step, so screens of
+    slightly different sizes share cached renditions.
+    """
+    if not value or value <= 0:
+        value = default
+    value = -(-value // RENDITION_SIZE_STEP) * RENDITION_SIZE_STEP
+    return min(value, RENDITION_MAX_SIDE)
+
+
+class RenditionCache:
+    """
+    Bounded on-disk cache of resized photos. Keys include the source file's
+    mtime and size, so edited photos get fresh renditions. Hits refresh the
+    file's mtime and the least recently used files are evicted first.
+    """
+
+    def __init__(self, cache_dir: Path):
+        self.cache_dir = cache_dir
+        self._lock = threading.Lock()
+        self._total_bytes: Optional[int] = None
+
+    @staticmethod
+    def key(rel_path: str, stat: os.stat_result, width: int, height: int,
+            fmt: str, quality: int) -> str:
+        raw = f"{rel_path}|{stat.st_mtime_ns}|{stat.st_size}|{width}x{height}|{fmt}|{quality}"
+        return hashlib.sha1(raw.encode('utf-8')).hexdigest()
+
+    def _path(self, key: str, fmt: str) -> Path:
+        return self.cache_dir / key[:2] / f"{key}.{fmt}"
+
+    def get(self, key: str, fmt: str) -> Optional[Path]:
+        path = self._path(key, fmt)
+        try:
+            os.utime(path)
+            return path
+        except OSError:
+            return None
+
+    def put(self, key: str, fmt: str, data: bytes, max_bytes: int) -> None:
+        path = self._path(key, fmt)
+        try:
+            path.parent.mkdir(parents=True, exist_ok=True)
+            # Unique temp name: two requests may render the same key at once
+            fd, tmp_path = tempfile.mkstemp(dir=path.parent, suffix='.tmp')
+            try:
+                with os.fdopen(fd, 'wb') as f:
+                    f.write(data)
+                try:
+                    old_size = path.stat().st_size
+                except OSError:
+                    old_size = 0
+                os.replace(tmp_path, path)
+            except BaseException:
+                try:
+                    os.unlink(tmp_path)
+                except OSError:
+                    pass
+                raise
+        except OSError as e:
+            logger.warning(f"Failed to cache rendition: {e}")
+            return
+
+        with self._lock:
+            if self._total_bytes is None:
+                self._total_bytes = sum(size for _, size, _ in self._files())
+            else:
+                self._total_bytes += len(data) - old_size
+            if self._total_bytes > max_bytes:
+                self._evict(max_bytes)
+
+    def _files(self) -> List[Tuple[float, int, Path]]:
+        files = []
+        for path in self.cache_dir.glob('*/*.*'):
+            try:
+                stat = path.stat()
+                files.append((stat.st_mtime, stat.st_size, path))
+            except OSError:
+                continue
+        return files
+
+    def _evict(self, max_bytes: int) -> None:
+        """Drop least recently used renditions until the cache is at 90% of its limit."""
+        target = max_bytes * 0.9
+        files = sorted(self._files())
+        total = sum(size for _, size, _ in files)
+        removed = 0
+        for _, size, path in files:
+            if total <= target:
+                break
+            try:
+                path.unlink()
+                total -= size
+                removed += 1
+            except OSError:
+                continue
+        self._total_bytes = total
+        logger.info(f"Evicted {removed} renditions, cache is now {total // (1024 * 1024)} MB")
+
 
-CONFIG_FILE = Path("/app/config.json")
-if not CONFIG_FILE.exists():
-    CONFIG_FILE = Path("config.json")
+rendition_cache = RenditionCache(RENDITION_CACHE_DIR)
+
+
+def render_photo(source: Path, width: int, height: int, fmt: str, quality: int) -> Optional[bytes]:
+    """
+    Resize a photo to fit within width x height, apply its EXIF orientation,
+    and re-encode it without metadata. Returns None for images that should be
+    served as-is (animated GIFs, unreadable files).
+    """
+    with _rendition_slots:
+        try:
+            with Image.open(source) as img:
+                if getattr(img, 'is_animated', False):
+                    return None
+
+                # Let the JPEG decoder downscale while decoding (much faster).
+                # Square box because the orientation may still swap the sides.
+                longest = max(width, height)
+                img.draft('RGB', (longest, longest))
+                icc_profile = img.info.get('icc_profile')
+                img = ImageOps.exif_transpose(img)
+                img.thumbnail((width, height), Image.LANCZOS)
+
+                # The profile is stripped below, so bake it into sRGB pixels
+                # first or wide-gamut photos would come out washed out.
+                if icc_profile:
+                    try:
+                        img = ImageCms.profileToProfile(
+                            img, ImageCms.ImageCmsProfile(io.BytesIO(icc_profile)),
+                            _srgb_profile(),
+                            outputMode='RGBA' if 'A' in img.getbands() else 'RGB')
+                    except Exception as e:
+                        logger.debug(f"Could not convert {source} to sRGB: {e}")
+
+                if fmt == 'jpeg' and img.mode != 'RGB':
+                    img = img.convert('RGB')
+                elif img.mode not in ('RGB', 'RGBA'):
+                    img = img.convert('RGBA' if 'A' in img.getbands() else 'RGB')
+
+                output = io.BytesIO()
+                # No exif/icc arguments: metadata is stripped from the rendition
+                img.save(output, format=fmt.upper(), quality=quality, optimize=fmt == 'jpeg')
+                return output.getvalue()
+        except Exception as e:
+            logger.warning(f"Failed to render {source}: {e}")
+            return None
 
-DEFAULT_CONFIG = {
-    "home_assistant_url": "http://homeassistant:8123",
-    "photos_folder": "/media",
-    "photos_source": "media",
-    "idle_timeout_seconds": 60,
-    "slide_interval_seconds": 5,
-    "clock_position": "bottom-center",
-    "weather_entity": "",
-    "media_player_entity": "",
-    "media_player_sources": ""
-}
 
 # ============================================================================
-# HELPER FUNCTIONS
+# PHOTO FILTERS
 # ============================================================================
 
-def load_config() -> Dict[str, Any]:
-    """Load configuration from JSON file, or return defaults if file doesn't exist."""
-    try:
-        if CONFIG_FILE.exists():
-            with open(CONFIG_FILE, 'r') as f:
-                config = json.load(f)
-                return {**DEFAULT_CONFIG, **config}
-        return DEFAULT_CONFIG.copy()
-    except (json.JSONDecodeError, IOError) as e:
-        logger.error(f"Failed to load config: {e}")
-        return DEFAULT_CONFIG.copy()
+FILTER_FIELDS = ('album', 'keyword', 'name', 'rating', 'date', 'orientation', 'resolution')
 
 
-def _gps_to_decimal(coords, ref) -> Optional[float]:
-    """Convert GPS EXIF coordinates (degrees, minutes, seconds) to decimal."""
-    if not coords or not ref:
-        return None
-    try:
-        degrees = float(coords[0])
-        minutes = float(coords[1])
-        seconds = float(coords[2])
-        decimal = degrees + minutes / 60 + seconds / 3600
-        if ref in ('S', 'W'):
-            decimal = -decimal
-        return decimal
-    except (TypeError, ValueError, ZeroDivisionError):
-        return None
+class FilterRuleError(ValueError):
+    pass
 
 
-def extract_exif(file_path: str) -> Dict[str, Any]:
-    """Extract date taken and raw GPS coordinates from image EXIF data."""
-    if not HAS_PILLOW:
-        return {}
+def _photo_rel_path(photo: Dict[str, Any]) -> str:
+    return urllib.parse.unquote(photo['url'][len('/photos/'):])
+
+
+def _parse_filter_date(value: str, end: bool) -> str:
+    """Expand 2019 / 2019-06 / 2019-06-05 to the first (or last) day it covers."""
+    parts = value.split('-')
     try:
-        img = Image.open(file_path)
-        exif_data = img._getexif()
-        if not exif_data:
-            return {}
+        if len(parts) == 1:
+            return f"{int(parts[0]):04d}-{12 if end else 1:02d}-{31 if end else 1:02d}"
+        if len(parts) == 2:
+            return f"{int(parts[0]):04d}-{int(parts[1]):02d}-{31 if end else 1:02d}"
+        return datetime.strptime(value, "%Y-%m-%d").date().isoformat()
+    except ValueError:
+        raise FilterRuleError(f"invalid date '{value}'")
+
+
+def _filter_predicate(field: str, value: str):
+    """Build the test for one rule; a photo missing the field never matches."""
+    if field == 'album':
+        album = value.strip('/')
+        return lambda p: _in_album(p, album)
+
+    if field == 'keyword':
+        keyword = value.lower()
+        return lambda p: keyword in (k.lower() for k in p['exif'].get('keywords', []))
+
+    if field == 'name':
+        pattern = value.lower()
+        if '/' in pattern:
+            return lambda p: fnmatch.fnmatchcase(_photo_rel_path(p).lower(), pattern)
+        return lambda p: fnmatch.fnmatchcase(Path(_photo_rel_path(p)).name.lower(), pattern)
+
+    if field == 'rating':
+        try:
+            minimum = int(value.lstrip('>='))
+        except ValueError:
+            raise FilterRuleError(f"invalid rating '{value}'")
+        return lambda p: p['exif'].get('rating', -2) >= minimum
+
+    if field == 'date':
+        start, separator, stop = value.partition('..')
+        if not separator:
+            stop = start
+        first = _parse_filter_date(start, False) if start else '0000-01-01'
+        last = _parse_filter_date(stop, True) if stop else '9999-12-31'
+        return lambda p: 'taken' in p['exif'] and first <= p['exif']['taken'][:10] <= last
+
+    if field == 'orientation':
+        if value not in ('landscape', 'portrait', 'square'):
+            raise FilterRuleError("orientation must be landscape, portrait or square")
+
+        def orientation(p):
+            width, height = p.get('width'), p.get('height')
+            if not width or not height:
+                return False
+            shape = 'square' if width == height else 'landscape' if width > height else 'portrait'
+            return shape == value
+        return orientation
+
+    if field == 'resolution':
+        match = re.fullmatch(r'(\d+)x(\d+)', value)
+        if not match:
+            raise FilterRuleError("resolution must look like 1920x1080")
+        long_side, short_side = sorted(map(int, match.groups()), reverse=True)
+        # Compared regardless of orientation: 1920x1080 also admits 1080x1920
+        return lambda p: (bool(p.get('width') and p.get('height')) and
+                          max(p['width'], p['height']) >= long_side and
+                          min(p['width'], p['height']) >= short_side)
+
+    raise FilterRuleError(f"unknown field '{field}' (expected one of {', '.join(FILTER_FIELDS)})")
+
+
+@lru_cache(maxsize=8)
+def compile_photo_filters(rules: Tuple[str, ...]):
+    """
+    Turn the photo_filters option into a single predicate.
 
-        result = {}
+    Each rule is "[exclude] <field>:<value>". A photo plays when, for every
+    field that has include rules, it matches at least one of them, and it
+    matches no exclude rule. Invalid rules are logged and ignored.
+    """
+    includes: Dict[str, List[Any]] = {}
+    excludes: List[Any] = []
+    for rule in rules:
+        text = rule.strip()
+        if not text:
+            continue
+        negate = False
+        verb, _, rest = text.partition(' ')
+        if verb.lower() in ('include', 'exclude') and rest:
+            negate, text = verb.lower() == 'exclude', rest.strip()
+        field, _, value = text.partition(':')
+        try:
+            predicate = _filter_predicate(field.strip().lower(), value.strip())
+        except FilterRuleError as e:
+            logger.warning(f"Ignoring photo filter '{rule}': {e}")
+            continue
+        if negate:
+            excludes.append(predicate)
+        else:
+            includes.setdefault(field.strip().lower(), []).append(predicate)
+
+    def matches(photo: Dict[str, Any]) -> bool:
+        if any(exclude(photo) for exclude in excludes):
+            return False
+        return all(any(include(photo) for include in group) for group in includes.values())
+    return matches
 
-        # DateTimeOriginal (EXIF tag 36867)
-        date_taken = exif_data.get(36867)
-        if date_taken:
-            try:
-                dt = datetime.strptime(date_taken, "%Y:%m:%d %H:%M:%S")
-                result['date'] = f"{dt.strftime('%B')} {dt.day}, {dt.year}"
-            except (ValueError, TypeError):
-                pass
 
-        # GPSInfo (EXIF tag 34853) -- return raw coordinates for geocoding
-        gps_info = exif_data.get(34853)
-        if gps_info:
-            lat = _gps_to_decimal(gps_info.get(2), gps_info.get(1))
-            lng = _gps_to_decimal(gps_info.get(4), gps_info.get(3))
-            if lat is not None and lng is not None:
-                result['lat'] = lat
-                result['lng'] = lng
+# ============================================================================
+# DUPLICATE PHOTOS
+# ============================================================================
 
-        return result
-    except Exception:
-        return {}
+class DuplicateFinder:
+    """
+    Groups near-identical images (burst shots, copies in several albums) by
+    the Hamming distance between their perceptual hashes.
 
+    Two 64-bit hashes within distance d agree exactly on at least one of d+1
+    slices of the hash (pigeonhole), so only photos sharing a slice are
+    compared instead of every pair. Groups are cached until the index changes.
+    """
 
-# --------------- Reverse geocoding with disk cache ---------------
+    def __init__(self, index: PhotoIndex):
+        self.index = index
+        self._lock = threading.Lock()
+        self._cache: Optional[Tuple[int, int, List[List[str]]]] = None
+
+    @staticmethod
+    def _group(hashes: Dict[str, int], distance: int) -> List[List[str]]:
+        paths = sorted(hashes)
+        parent = list(range(len(paths)))
+
+        def find(i: int) -> int:
+            while parent[i] != i:
+                parent[i] = parent[parent[i]]
+                i = parent[i]
+            return i
+
+        slices = distance + 1
+        bounds = [(64 * s // slices, 64 * (s + 1) // slices) for s in range(slices)]
+        for low, high in bounds:
+            buckets: Dict[int, List[int]] = {}
+            mask = (1 << (high - low)) - 1
+            for i, path in enumerate(paths):
+                buckets.setdefault((hashes[path] >> low) & mask, []).append(i)
+            for members in buckets.values():
+                for a, i in enumerate(members):
+                    for j in members[a + 1:]:
+                        if find(i) != find(j) and bin(hashes[paths[i]] ^ hashes[paths[j]]).count('1') <= distance:
+                            parent[find(i)] = find(j)
+
+        groups: Dict[int, List[str]] = {}
+        for i, path in enumerate(paths):
+            groups.setdefault(find(i), []).append(path)
+        return [group for group in groups.values() if len(group) > 1]
+
+    def groups(self, distance: int) -> List[List[str]]:
+        """Return groups (of two or more photo paths) of near-duplicates."""
+        with self._lock:
+            generation = self.index.generation
+            if self._cache and self._cache[:2] == (generation, distance):
+                return self._cache[2]
+        groups = self._group(self.index.perceptual_hashes(), distance)
+        with self._lock:
+            self._cache = (generation, distance, groups)
+        return groups
+
+
+duplicate_finder = DuplicateFinder(photo_index)
+
+
+def _duplicate_rank(photo: Dict[str, Any]) -> Tuple:
+    """Sort key for picking the photo of a duplicate group that plays: best first."""
+    exif = photo.get('exif', {})
+    pixels = (photo.get('width') or 0) * (photo.get('height') or 0)
+    return (not exif.get('favorite'), -(exif.get('rating') or 0), -pixels, photo['url'])
+
+
+def _duplicate_distance(config: Dict[str, Any]) -> int:
+    return max(0, min(int(config.get('duplicate_distance', 6)), 20))
+
+
+def duplicate_siblings(rel_paths: set, config: Dict[str, Any]) -> set:
+    """Paths that are near-duplicates of any of the given photos."""
+    if not config.get('duplicate_suppression', True):
+        return set()
+    siblings = set()
+    for group in duplicate_finder.groups(_duplicate_distance(config)):
+        if rel_paths.intersection(group):
+            siblings.update(group)
+    return siblings - rel_paths
+
+
+def suppress_duplicates(photos: List[Dict[str, Any]], config: Dict[str, Any]) -> List[Dict[str, Any]]:
+    """
+    Keep only the best photo (favourite, then highest rating, then highest
+    resolution) of each group of near-duplicates among the given photos.
+    """
+    if not config.get('duplicate_suppression', True):
+        return photos
+    distance = _duplicate_distance(config)
+    by_path = {_photo_rel_path(photo): photo for photo in photos}
+    dropped = set()
+    for group in duplicate_finder.groups(distance):
+        members = sorted((by_path[path] for path in group if path in by_path), key=_duplicate_rank)
+        dropped.update(photo['url'] for photo in members[1:])
+    return [photo for photo in photos if photo['url'] not in dropped]
 
-GEOCACHE_FILE = Path("/app/geocache.json")
 
+# ============================================================================
+# PHOTO SELECTION
+# ============================================================================
 
-def _load_geocache() -> Dict[str, Optional[str]]:
-    try:
-        if GEOCACHE_FILE.exists():
-            with open(GEOCACHE_FILE, 'r') as f:
-                return json.load(f)
-    except Exception:
-        pass
-    return {}
+ON_THIS_DAY_WINDOWS = {'day': 0, 'week': 3}
 
 
-def _save_geocache(cache: Dict[str, Optional[str]]) -> None:
-    try:
-        GEOCACHE_FILE.parent.mkdir(parents=True, exist_ok=True)
-        with open(GEOCACHE_FILE, 'w') as f:
-            json.dump(cache, f, indent=2)
-    except Exception:
-        pass
+def _anniversary(taken: date, year: int) -> date:
+    """The photo's date in the given year; leap day photos fall on February 28th otherwise."""
+    if (taken.month, taken.day) == (2, 29) and not calendar.isleap(year):
+        return date(year, 2, 28)
+    return taken.replace(year=year)
 
 
-def _nominatim_reverse(lat: float, lng: float) -> Optional[str]:
-    """Reverse-geocode via OpenStreetMap Nominatim (free, no API key)."""
+def _years_ago(taken: str, today: date, window_days: int) -> Optional[int]:
+    """
+    How many years ago a photo was taken if it falls on today's date (give or
+    take window_days) in an earlier year, else None. Distances are counted in
+    the calendar around today, so windows wrap around New Year (on January 2nd
+    a week window includes December 30th) and span February 29th correctly.
+    """
     try:
-        url = (
-            f"https://nominatim.openstreetmap.org/reverse?"
-            f"format=json&lat={lat}&lon={lng}&zoom=10&accept-language=en"
-        )
-        req = urllib.request.Request(url, headers={
-            'User-Agent': 'HAScreensaver/1.1'
-        })
-        with urllib.request.urlopen(req, timeout=5) as resp:
-            data = json.loads(resp.read())
-
-        addr = data.get('address', {})
-        city = (addr.get('city') or addr.get('town') or
-                addr.get('village') or addr.get('hamlet') or
-                addr.get('municipality') or '')
-        country = addr.get('country', '')
-
-        if city and country:
-            return f"{city}, {country}"
-        return city or country or None
-    except Exception as e:
-        logger.warning(f"Reverse geocoding failed for {lat},{lng}: {e}")
+        taken_date = date.fromisoformat(taken[:10])
+    except ValueError:
         return None
+    # The nearest anniversary may be in last year's December or next January
+    year = min(
+        (today.year - 1, today.year, today.year + 1),
+        key=lambda y: abs((_anniversary(taken_date, y) - today).days)
+    )
+    if abs((_anniversary(taken_date, year) - today).days) > window_days:
+        return None
+    years = year - taken_date.year
+    return years if years >= 1 else None
 
 
-def _format_coords(lat: float, lng: float) -> str:
-    """Fallback: format coordinates as a human-readable string."""
-    lat_dir = 'N' if lat >= 0 else 'S'
-    lng_dir = 'E' if lng >= 0 else 'W'
-    return f"{abs(lat):.1f}\u00b0{lat_dir}, {abs(lng):.1f}\u00b0{lng_dir}"
+def select_photos(photos: List[Dict[str, Any]], config: Dict[str, Any],
+                  today: Optional[date] = None) -> List[Dict[str, Any]]:
+    """
+    Apply the photo_selection mode to the playable photos. In on_this_day
+    mode, photos taken on today's date (or within the week around it) in past
+    years are returned with a years_ago field; when there are fewer than
+    on_this_day_min_photos of them the whole pool plays, still marking the
+    memories in it.
+    """
+    if config.get('photo_selection') != 'on_this_day':
+        return photos
 
+    today = today or date.today()
+    window_days = ON_THIS_DAY_WINDOWS.get(config.get('on_this_day_window'), 0)
+    memories, selection = [], []
+    for photo in photos:
+        years = _years_ago(photo['exif'].get('taken', ''), today, window_days)
+        if years is not None:
+            photo = {**photo, 'years_ago': years}
+            memories.append(photo)
+        selection.append(photo)
 
-# -----------------------------------------------------------------
+    if len(memories) >= int(config.get('on_this_day_min_photos', 5)):
+        return memories
+    return selection
 
-def get_image_files(folder_path: str) -> List[Dict[str, Any]]:
-    """
-    Scan a folder for image files and return their URLs with EXIF metadata.
-    GPS coordinates are reverse-geocoded to city/country names (cached to disk).
-    """
-    image_extensions = {'.jpg', '.jpeg', '.png', '.gif', '.webp'}
 
-    photos = []
-    folder = Path(folder_path)
+# ============================================================================
+# PLAYBACK QUEUES
+# ============================================================================
 
-    if not folder.exists():
-        logger.warning(f"Photos folder does not exist: {folder_path}")
-        return []
+QUEUE_FILE = Path("/data/playback_queues.json")
+QUEUE_VERSION = 1
+QUEUE_MAX_SCREENS = 20
+QUEUE_SAVE_SECONDS = 30
+QUEUE_MAX_COUNT = 50
+# How far ahead in a round to look for a partner photo
+PAIR_LOOKAHEAD = 200
 
-    if not folder.is_dir():
-        logger.warning(f"Photos path is not a directory: {folder_path}")
-        return []
 
-    try:
-        for file_path in folder.iterdir():
-            if file_path.is_file():
-                ext = file_path.suffix.lower()
-                if ext in image_extensions:
-                    photo_url = f"/photos/{file_path.name}"
-                    exif = extract_exif(str(file_path))
-                    photos.append({"url": photo_url, "exif": exif})
-
-        # Batch reverse-geocode any new GPS coordinates
-        geocache = _load_geocache()
-        needs_save = False
-
-        for photo in photos:
-            exif = photo.get('exif', {})
-            lat, lng = exif.pop('lat', None), exif.pop('lng', None)
-            if lat is None or lng is None:
-                continue
+def photo_shape(photo: Dict[str, Any]) -> Optional[str]:
+    """'portrait' or 'landscape' for images with known dimensions, else None."""
+    width, height = photo.get('width'), photo.get('height')
+    if photo.get('type') == 'video' or not width or not height or width == height:
+        return None
+    return 'portrait' if height > width else 'landscape'
 
-            cache_key = f"{lat:.2f},{lng:.2f}"
-            if cache_key not in geocache:
-                geocache[cache_key] = _nominatim_reverse(lat, lng)
-                needs_save = True
-                time.sleep(1)  # Nominatim rate limit: 1 req/s
 
-            location = geocache.get(cache_key)
-            exif['location'] = location if location else _format_coords(lat, lng)
+def _pair_distance(photo: Dict[str, Any], other: Dict[str, Any]) -> float:
+    """How far apart two photos are, in days; photos from the same place count as one day."""
+    a, b = photo['exif'], other['exif']
+    days = 365.0
+    if a.get('taken') and b.get('taken'):
+        try:
+            gap = datetime.fromisoformat(a['taken'][:19]) - datetime.fromisoformat(b['taken'][:19])
+            days = abs(gap.total_seconds()) / 86400
+        except ValueError:
+            pass
+    if a.get('location') and a.get('location') == b.get('location'):
+        days = min(days, 1.0)
+    return days
 
-        if needs_save:
-            _save_geocache(geocache)
 
-        logger.info(f"Found {len(photos)} photos in {folder_path}")
-        return sorted(photos, key=lambda p: p["url"])
+def shuffle_weight(photo: Dict[str, Any], config: Dict[str, Any], today: Optional[date] = None) -> float:
+    """
+    How strongly a photo is pulled towards the front of each round. 1 is
+    neutral; the shuffle_*_weight options multiply it for favourites (marked
+    on a screen, or five stars), higher ratings and recently taken photos.
+    """
+    exif = photo.get('exif', {})
+    weight = 1.0
+    rating = exif.get('rating') or 0
+    if exif.get('favorite') or rating >= 5:
+        weight *= float(config.get('shuffle_favorite_weight', 1))
+    if rating > 0:
+        weight *= 1 + (float(config.get('shuffle_rating_weight', 1)) - 1) * min(rating, 5) / 5
+    if 'taken' in exif:
+        today = today or date.today()
+        try:
+            age = (today - date.fromisoformat(exif['taken'][:10])).days
+        except ValueError:
+            age = None
+        if age is not None and age <= int(config.get('shuffle_recent_days', 30)):
+            weight *= float(config.get('shuffle_recent_weight', 1))
+    return max(weight, 0.01)
+
+
+class PlaybackQueues:
+    """
+    Shuffled playback order for each screen (or one shared by all screens).
 
-    except PermissionError as e:
-        logger.error(f"Permission denied reading folder {folder_path}: {e}")
-        return []
-    except Exception as e:
-        logger.error(f"Error scanning photos folder: {e}")
-        return []
+    A queue plays every photo once per round before any repeats. Rounds are
+    a weighted shuffle (Efraimidis-Spirakis), so heavier photos tend to come
+    early without ever playing twice in a round. Photos added mid-round are
+    slotted into what's left of it and removed ones are dropped. Queues are
+    saved periodically so a restart resumes the round instead of starting over.
+    """
+
+    def __init__(self, queue_file: Path):
+        self.queue_file = queue_file
+        self._lock = threading.Lock()
+        self._queues: Optional[Dict[str, Dict[str, Any]]] = None
+        self._last_save = 0.0
+
+    def _load(self) -> Dict[str, Dict[str, Any]]:
+        if self._queues is None:
+            self._queues = {}
+            try:
+                if self.queue_file.exists():
+                    with open(self.queue_file, 'r') as f:
+                        data = json.load(f)
+                    if data.get('version') == QUEUE_VERSION:
+                        self._queues = data.get('queues', {})
+            except Exception as e:
+                logger.warning(f"Ignoring unreadable playback queues: {e}")
+        return self._queues
+
+    def save(self) -> None:
+        """Write the queues atomically."""
+        with self._lock:
+            data = json.dumps({'version': QUEUE_VERSION, 'queues': self._load()})
+            self._last_save = time.time()
+        try:
+            self.queue_file.parent.mkdir(parents=True, exist_ok=True)
+            tmp_file = self.queue_file.with_suffix('.tmp')
+            with open(tmp_file, 'w') as f:
+                f.write(data)
+            os.replace(tmp_file, self.queue_file)
+        except Exception as e:
+            logger.warning(f"Failed to save playback queues: {e}")
+
+    def _queue(self, screen: str) -> Dict[str, Any]:
+        queues = self._load()
+        if screen not in queues:
+            if len(queues) >= QUEUE_MAX_SCREENS:
+                # Forget the screen that asked least recently
+                del queues[min(queues, key=lambda s: queues[s].get('used', 0))]
+            queues[screen] = {'remaining': [], 'played': []}
+        return queues[screen]
+
+    @staticmethod
+    def _reconcile(state: Dict[str, Any], weights: Dict[str, float]) -> None:
+        """Drop photos that left the pool and slot new ones into the current round."""
+        remaining = [url for url in state['remaining'] if url in weights]
+        played = [url for url in state['played'] if url in weights]
+        if not remaining and not played:
+            # A new queue gets a properly shuffled first round
+            PlaybackQueues._new_round(state, weights)
+            return
+        known = set(remaining) | set(played)
+        for url in weights:
+            if url not in known:
+                # random() ** weight leans towards 0, i.e. the front of the round;
+                # len + 1 slots so the end of the round can be picked too
+                remaining.insert(int((len(remaining) + 1) * random.random() ** weights[url]), url)
+        state['remaining'], state['played'] = remaining, played
+
+    @staticmethod
+    def _new_round(state: Dict[str, Any], weights: Dict[str, float]) -> None:
+        last = state['played'][-1] if state['played'] else None
+        order = sorted(weights, key=lambda url: random.random() ** (1 / weights[url]), reverse=True)
+        # Don't show the last photo of a round again straight away
+        if len(order) > 1 and order[0] == last:
+            order[0], order[1] = order[1], order[0]
+        state['remaining'], state['played'] = order, []
+
+    @staticmethod
+    def _take_partner(state: Dict[str, Any], pool: Dict[str, Dict[str, Any]],
+                      photo: Dict[str, Any]) -> Optional[Dict[str, Any]]:
+        """
+        Find the photo later in this round that best goes next to the given
+        one (same shape, closest in time or place) and take it off the queue.
+        """
+        shape = photo_shape(photo)
+        candidates = [
+            (i, url) for i, url in enumerate(state['remaining'][:PAIR_LOOKAHEAD])
+            if photo_shape(pool[url]) == shape
+        ]
+        if not candidates:
+            return None
+        index, url = min(candidates, key=lambda c: (_pair_distance(photo, pool[c[1]]), c[0]))
+        del state['remaining'][index]
+        state['played'].append(url)
+        return pool[url]
+
+    def next(self, screen: str, photos: List[Dict[str, Any]], config: Dict[str, Any],
+             count: int, screen_shape: Optional[str] = None) -> List[Dict[str, Any]]:
+        """
+        Take the next count photos off the screen's queue. With the paired
+        layout, photos whose shape doesn't suit the screen (portrait photos on
+        a landscape screen and vice versa) come with a 'partner' to show
+        alongside; the partner counts as played.
+        """
+        pool = {photo['url']: photo for photo in photos}
+        weights = {url: shuffle_weight(photo, config) for url, photo in pool.items()}
+        pair_shape = {'landscape': 'portrait', 'portrait': 'landscape'}.get(screen_shape)
+        if config.get('photo_layout') != 'paired':
+            pair_shape = None
+        upcoming = []
+        with self._lock:
+            state = self._queue(screen)
+            self._reconcile(state, weights)
+            while pool and len(upcoming) < count:
+                if not state['remaining']:
+                    self._new_round(state, weights)
+                url = state['remaining'].pop(0)
+                state['played'].append(url)
+                photo = pool[url]
+                if pair_shape and photo_shape(photo) == pair_shape:
+                    partner = self._take_partner(state, pool, photo)
+                    if partner:
+                        photo = {**photo, 'partner': partner}
+                upcoming.append(photo)
+            state['used'] = time.time()
+            due = time.time() - self._last_save >= QUEUE_SAVE_SECONDS
+        if due:
+            self.save()
+        return upcoming
+
+
+playback_queues = PlaybackQueues(QUEUE_FILE)
 
 
 # ============================================================================
 # API ROUTES
 # ============================================================================
 
+def same_origin(view: Callable) -> Callable:
+    """
+    Reject browser requests sent from another site. Requests without an Origin
+    header (curl, Home Assistant automations) are allowed. Behind ingress the
+    Host is the add-on's internal address, so the forwarded host counts too.
+    """
+    @wraps(view)
+    def wrapper(*args, **kwargs):
+        origin = request.headers.get('Origin')
+        if origin:
+            origin_host = urllib.parse.urlsplit(origin).netloc
+            allowed = {request.host, request.headers.get('X-Forwarded-Host')}
+            if origin_host not in allowed:
+                logger.warning(f"Rejected {request.method} {request.path} from {origin}")
+                return jsonify({"error": "Cross-origin request not allowed"}), 403
+        return view(*args, **kwargs)
+    return wrapper
+
+
 @app.route('/api/config', methods=['GET'])
 def get_config():
     """GET /api/config - Return current configuration."""
     config = load_config()
-    return jsonify(config)
+    return jsonify(public_config(config))
+
+
+def _indexed_photos(config: Dict[str, Any]) -> List[Dict[str, Any]]:
+    """Return the photo index for the configured folder."""
+    photos_folder = config.get('photos_folder', '/media')
+    max_depth = int(config.get('max_folder_depth', 10))
+    return photo_index.photos(photos_folder, max_depth)
+
+
+def _is_playable(photo: Dict[str, Any], config: Dict[str, Any]) -> bool:
+    """True if the photo should be part of the slideshow under the current config."""
+    if photo_state.is_hidden(_photo_rel_path(photo)):
+        return False
+    if photo.get('type') == 'video':
+        if not config.get('video_enabled', True):
+            return False
+        duration = photo.get('duration')
+        max_seconds = config.get('video_max_seconds', 30)
+        if (config.get('video_long_clip_action') == 'skip'
+                and duration is not None and duration > max_seconds):
+            return False
+    rules = config.get('photo_filters') or []
+    if isinstance(rules, str):
+        rules = rules.splitlines()
+    return compile_photo_filters(tuple(rules))(photo)
+
+
+def _playable_photos(config: Dict[str, Any],
+                     photos: Optional[List[Dict[str, Any]]] = None) -> List[Dict[str, Any]]:
+    if photos is None:
+        photos = _indexed_photos(config)
+    return [p for p in photos if _is_playable(p, config)]
+
+
+def _rotation(config: Dict[str, Any],
+              photos: Optional[List[Dict[str, Any]]] = None) -> List[Dict[str, Any]]:
+    """The photos the slideshow plays: playable, de-duplicated and selected."""
+    return select_photos(suppress_duplicates(_playable_photos(config, photos), config), config)
+
+
+def publish_photo_changes(changed: List[Dict[str, Any]], removed: List[str]) -> None:
+    """
+    Push library changes to connected screens. Changed photos that are no
+    longer playable under the current config are sent as removals.
+    """
+    config = load_config()
+    # Duplicates and selection depend on the whole library, so check against
+    # it. This runs inside index updates, so read the index without scanning.
+    selected = {p['url']: p for p in _rotation(config, photo_index.current())}
+    playable = [selected[p['url']] for p in changed if p['url'] in selected]
+    playable_urls = {p['url'] for p in playable}
+    removed = removed + [p['url'] for p in changed if p['url'] not in playable_urls]
+    # A new or better copy of a photo can push an older one out of rotation
+    siblings = duplicate_siblings({_photo_rel_path(p) for p in changed}, config)
+    removed += [_photo_url(path) for path in sorted(siblings) if _photo_url(path) not in selected]
+    event_bus.publish('photos', {'changed': playable, 'removed': removed})
+
+
+def _in_album(photo: Dict[str, Any], album: str) -> bool:
+    """True if the photo is in the album or one of its subfolders."""
+    return photo['album'] == album or photo['album'].startswith(album + '/')
 
 
 @app.route('/api/photos', methods=['GET'])
 def get_photos():
-    """GET /api/photos - Return list of photo URLs with EXIF metadata."""
+    """
+    GET /api/photos - Return list of photo and video URLs with album and EXIF metadata.
+    Optional ?album=<folder> limits the list to that album and its subfolders.
+    """
     config = load_config()
-    photos_folder = config.get('photos_folder', '/media')
-    photos = get_image_files(photos_folder)
+    photos = _rotation(config)
+    album = request.args.get('album', '').strip('/')
+    if album:
+        photos = [p for p in photos if _in_album(p, album)]
     return jsonify(photos)
 
 
-@app.route('/api/weather', methods=['GET'])
-def get_weather():
-    """GET /api/weather - Proxy weather data from Home Assistant."""
+def _photo_action_target(config: Dict[str, Any]):
+    """Resolve the {"url": "/photos/..."} in a photo action request to an indexed photo."""
+    data = request.get_json(silent=True)
+    if not data or not isinstance(data.get('url'), str):
+        return None, (jsonify({"error": "Request body must include the photo url"}), 400)
+    url = data['url']
+    if not url.startswith('/photos/'):
+        return None, (jsonify({"error": "Not a photo url"}), 400)
+    rel_path = urllib.parse.unquote(url[len('/photos/'):])
+    _indexed_photos(config)
+    if photo_index.get(rel_path) is None:
+        return None, (jsonify({"error": "Photo not found"}), 404)
+    return (rel_path, data), None
+
+
+def _publish_photo(rel_path: str) -> None:
+    entry = photo_index.get(rel_path)
+    if entry is not None:
+        publish_photo_changes([_photo_response(rel_path, entry)], [])
+
+
+@app.route('/api/photos/favorite', methods=['POST'])
+@same_origin
+def favorite_photo():
+    """POST /api/photos/favorite {"url": ..., "favorite": true|false} - Mark or unmark a favourite."""
+    target, error = _photo_action_target(load_config())
+    if error:
+        return error
+    rel_path, data = target
+    photo_state.set_favorite(rel_path, bool(data.get('favorite', True)))
+    _publish_photo(rel_path)
+    return jsonify({"success": True})
+
+
+@app.route('/api/photos/hide', methods=['POST'])
+@same_origin
+def hide_photo():
+    """POST /api/photos/hide {"url": ..., "hidden": true|false} - Take a photo out of rotation, or bring it back."""
+    target, error = _photo_action_target(load_config())
+    if error:
+        return error
+    rel_path, data = target
+    photo_state.set_hidden(rel_path, bool(data.get('hidden', True)))
+    _publish_photo(rel_path)
+    return jsonify({"success": True})
+
+
+@app.route('/api/photos/hidden', methods=['GET'])
+def get_hidden_photos():
+    """GET /api/photos/hidden - URLs of hidden photos."""
+    return jsonify([_photo_url(rel_path) for rel_path in photo_state.hidden()])
+
+
+@app.route('/api/photos/trash', methods=['POST'])
+@same_origin
+def trash_photo():
+    """POST /api/photos/trash {"url": ...} - Move a photo to the trash folder."""
     config = load_config()
-    weather_entity = config.get('weather_entity', '')
+    target, error = _photo_action_target(config)
+    if error:
+        return error
+    rel_path, _ = target
+    try:
+        moved_to = move_to_trash(config.get('photos_folder', '/media'), rel_path,
+                                 config.get('trash_folder', ''))
+    except OSError as e:
+        logger.error(f"Cannot move {rel_path} to trash: {e}")
+        return jsonify({"error": "Cannot move photo to trash"}), 500
+    logger.info(f"Moved {rel_path} to {moved_to}")
+    photo_state.forget(rel_path)
+    # The watcher drops it from the index; tell the screens right away
+    publish_photo_changes([], [_photo_url(rel_path)])
+    return jsonify({"success": True})
+
+
+@app.route('/api/queue', methods=['GET'])
+def get_queue():
+    """
+    GET /api/queue?screen=<id>&count=<n>&shape=<landscape|portrait> - Take the
+    next photos off a playback queue. Each screen has its own queue unless
+    shuffle_queue is 'shared'. The screen's shape lets the paired layout
+    put two photos side by side. Optional &album=<folder> plays from a
+    separate queue of that album's photos.
+    """
+    config = load_config()
+    try:
+        count = max(1, min(int(request.args.get('count', 10)), QUEUE_MAX_COUNT))
+    except ValueError:
+        return jsonify({"error": "count must be a number"}), 400
+    screen = request.args.get('screen', '').strip()[:64] or 'default'
+    if config.get('shuffle_queue') == 'shared':
+        screen = 'shared'
+    shape = request.args.get('shape')
+    photos = _rotation(config)
+    album = request.args.get('album', '').strip('/')
+    if album:
+        photos = [p for p in photos if _in_album(p, album)]
+        screen = f'{screen}/{album}'
+    return jsonify(playback_queues.next(screen, photos, config, count, shape))
+
+
+@app.route('/api/events', methods=['GET'])
+def get_events():
+    """
+    GET /api/events - Server-Sent Events stream of server-side changes.
+    Emits 'photos' events ({changed: [...], removed: [...]}) when the library
+    changes, and 'weather' and 'media' events (as returned by /api/weather
+    and /api/media) when Home Assistant reports a change. 'home_assistant'
+    events ({connected: bool}) say whether those changes are being followed,
+    and 'presence' events ({occupied, since, sleep}) follow the presence and
+    motion entities. A new stream starts with the current state of all of these.
+    ?screen=<id> registers the screen; add &named=1 when the id was chosen by
+    the user rather than generated. Beyond EVENTS_MAX_STREAMS streams the request is refused and screens poll.
+    """
+    config = load_config()
+    screen_id = request.args.get('screen', '')
+    if screen_id and not valid_screen_id(screen_id):
+        return jsonify({"error": f"screen must be 1-{SCREEN_ID_MAX_LENGTH} printable characters"}), 400
+    named = request.args.get('named') == '1'
+
+    # Take the slot now so concurrent connects can't overshoot the limit
+    subscription = event_bus.subscribe(limit=EVENTS_MAX_STREAMS)
+    if subscription is None:
+        logger.warning(f"Refusing event stream: {EVENTS_MAX_STREAMS} streams already open")
+        return jsonify({"error": "Too many screens connected"}), 503, {'Retry-After': '30'}
+    snapshot = [
+        ('config', public_config(config)),
+        ('home_assistant', {'connected': home_assistant.connected}),
+        ('presence', presence_monitor.status()),
+        ('weather', weather_payload(config)),
+        ('media', media_payload(config)),
+    ]
+
+    def stream():
+        # A screen counts as online while it holds a stream open
+        if screen_id:
+            screens.connect(screen_id, named)
+        try:
+            yield 'retry: 5000\n\n'
+            for event, data in snapshot:
+                yield f"event: {event}\ndata: {json.dumps(data)}\n\n"
+            while True:
+                try:
+                    event, data = subscription.get(timeout=EVENTS_KEEPALIVE_SECONDS)
+                except queue.Empty:
+                    # Comment line keeps proxies from closing an idle stream
+                    yield ': keepalive\n\n'
+                    continue
+                yield f"event: {event}\ndata: {json.dumps(data)}\n\n"
+        finally:
+            event_bus.unsubscribe(subscription)
+            if screen_id:
+                screens.disconnect(screen_id)
+
+    response = Response(stream(), mimetype='text/event-stream', headers={
+        'Cache-Control': 'no-cache',
+        'X-Accel-Buffering': 'no'
+    })
+    # Also frees the slot if the client leaves before the stream starts
+    response.call_on_close(lambda: event_bus.unsubscribe(subscription))
+    return response
 
-    if not weather_entity:
-        return jsonify(None)
 
-    supervisor_token = os.environ.get('SUPERVISOR_TOKEN', '')
-    if not supervisor_token:
-        logger.warning("No SUPERVISOR_TOKEN available for weather API")
-        return jsonify(None)
+@app.route('/api/screens', methods=['GET'])
+def get_screens():
+    """GET /api/screens - Screens that have connected, whether they are online and what they show."""
+    return jsonify([
+        {**screen, 'online': screen['streams'] > 0}
+        for screen in screens.all()
+    ])
 
-    try:
-        url = f'http://supervisor/core/api/states/{weather_entity}'
-        req = urllib.request.Request(url, headers={
-            'Authorization': f'Bearer {supervisor_token}',
-            'Content-Type': 'application/json'
-        })
-        with urllib.request.urlopen(req, timeout=5) as response:
-            data = json.loads(response.read())
-
-        attrs = data.get('attributes', {})
-        return jsonify({
-            'condition': data.get('state', ''),
-            'temperature': attrs.get('temperature'),
-            'temperature_unit': attrs.get('temperature_unit', '°C')
-        })
-    except Exception as e:
-        logger.error(f"Error fetching weather: {e}")
-        return jsonify(None)
 
+@app.route('/api/screens/<screen_id>/state', methods=['POST'])
+@same_origin
+def report_screen_state(screen_id: str):
+    """
+    POST /api/screens/<screen>/state - A screen reports whether the
+    screensaver is running ({"active": true, "photo": {url, album, ...}}).
+    """
+    body = request.get_json(silent=True) or {}
+    photo = body.get('photo')
+    if photo is not None and not isinstance(photo, dict):
+        return jsonify({"error": "photo must be an object"}), 400
+    screen = screens.report(screen_id, bool(body.get('active')), photo)
+    if screen is None:
+        return jsonify({"error": "Screen not connected"}), 404
+    return jsonify({**screen, 'online': True})
+
+
+@app.route('/api/screens/<screen_id>', methods=['DELETE'])
+@same_origin
+def forget_screen(screen_id: str):
+    """DELETE /api/screens/<screen> - Forget an offline screen and remove it from Home Assistant."""
+    if screens.get(screen_id) is None:
+        return jsonify({"error": "Screen not found"}), 404
+    if not screens.forget(screen_id):
+        return jsonify({"error": "Screen is online"}), 409
+    return jsonify({"success": True})
+
+
+@app.route('/api/screens/<screen_id>/ack', methods=['POST'])
+@same_origin
+def acknowledge_screen_command(screen_id: str):
+    """POST /api/screens/<screen>/ack - A screen acknowledges a command ({"id": 1, "error": null})."""
+    body = request.get_json(silent=True) or {}
+    if not isinstance(body.get('id'), int):
+        return jsonify({"error": "id required"}), 400
+    error = body.get('error')
+    if not screen_commands.acknowledge(body['id'], screen_id, str(error) if error else None):
+        return jsonify({"error": "Unknown or expired command"}), 404
+    return jsonify({"ok": True})
+
+
+@app.route('/api/screens/command', methods=['POST'])
+@app.route('/api/screens/<screen_id>/command', methods=['POST'])
+@same_origin
+def command_screens(screen_id: Optional[str] = None):
+    """
+    POST /api/screens[/<screen>]/command - Remote control one screen, or every
+    connected screen. Body: {"command": "start" | "stop" | "next" | "previous"},
+    {"command": "album", "album": "2023/Italy"} ("" for all photos) or
+    {"command": "photo", "url": "/photos/..."}. Waits up to ?wait=<seconds>
+    (default 5) for the screens to acknowledge.
+    """
+    body = request.get_json(silent=True) or {}
+    command = body.get('command')
+    if command not in REMOTE_COMMANDS:
+        return jsonify({"error": f"command must be one of: {', '.join(REMOTE_COMMANDS)}"}), 400
+    try:
+        wait = max(0.0, min(float(request.args.get('wait', COMMAND_ACK_SECONDS)), COMMAND_MAX_WAIT_SECONDS))
+    except ValueError:
+        return jsonify({"error": "wait must be a number"}), 400
 
-@app.route('/api/media', methods=['GET'])
-def get_media():
-    """GET /api/media - Return current media player state from Home Assistant."""
     config = load_config()
-    media_entity = config.get('media_player_entity', '')
+    args: Dict[str, Any] = {}
+    if command == 'album':
+        album = str(body.get('album') or '').strip('/')
+        if album and not any(_in_album(p, album) for p in _rotation(config)):
+            return jsonify({"error": "Album not found"}), 404
+        args['album'] = album
+    elif command == 'photo':
+        target, error = _photo_action_target(config)
+        if error:
+            return error
+        rel_path, _ = target
+        args['photo'] = _photo_response(rel_path, photo_index.get(rel_path))
+
+    if screen_id is not None:
+        screen = screens.get(screen_id)
+        if screen is None or not screen['streams']:
+            return jsonify({"error": "Screen not connected"}), 404
+        targets = {screen_id}
+    else:
+        targets = {screen['id'] for screen in screens.all() if screen['streams']}
+        if not targets:
+            return jsonify({"error": "No screens connected"}), 404
+
+    command_id = screen_commands.send(command, screen_id, args)
+    acks = screen_commands.wait(command_id, targets, wait)
+    results = {
+        target: {'acknowledged': target in acks, **acks.get(target, {'ok': False, 'error': None})}
+        for target in sorted(targets)
+    }
+    if all(result['ok'] for result in results.values()):
+        status = 200
+    elif all(result['acknowledged'] for result in results.values()):
+        status = 409
+    else:
+        status = 504
+    return jsonify({'id': command_id, 'command': command, 'screens': results}), status
+
+
+@app.route('/api/duplicates', methods=['GET'])
+def get_duplicates():
+    """
+    GET /api/duplicates - Groups of near-duplicate photos, best first. Only the
+    first photo of each group plays when duplicate_suppression is on.
+    """
+    config = load_config()
+    photos = {_photo_rel_path(p): p for p in _indexed_photos(config)}
+    distance = _duplicate_distance(config)
+    groups = []
+    for group in duplicate_finder.groups(distance):
+        members = sorted((photos[path] for path in group if path in photos), key=_duplicate_rank)
+        if len(members) > 1:
+            groups.append(members)
+    return jsonify(sorted(groups, key=lambda members: members[0]['url']))
+
+
+@app.route('/api/albums', methods=['GET'])
+def get_albums():
+    """GET /api/albums - Return every album (subfolder) with its photo count."""
+    config = load_config()
+    counts: Dict[str, int] = {}
+    for photo in _indexed_photos(config):
+        counts[photo['album']] = counts.get(photo['album'], 0) + 1
+    return jsonify([
+        {"name": name, "count": count} for name, count in sorted(counts.items())
+    ])
+
+
+@app.route('/api/admin/geocache', methods=['GET'])
+def get_geocache():
+    """GET /api/admin/geocache - Geocache size and geocoding queue status."""
+    return jsonify({
+        **geocache.stats(),
+        "pending": geocoding_worker.pending(),
+        "provider": geocoding_worker.provider.name,
+        "precision": geocoding_worker.precision,
+        "label": geocoding_worker.granularity
+    })
+
+
+def _geocache_scope() -> Optional[bool]:
+    """Parse the purge scope: True for negative results only, False for everything."""
+    body = request.get_json(silent=True) or {}
+    scope = request.args.get('scope') or body.get('scope') or 'negative'
+    return {'negative': True, 'all': False}.get(scope)
+
+
+@app.route('/api/admin/geocache', methods=['DELETE'])
+@same_origin
+def purge_geocache():
+    """
+    DELETE /api/admin/geocache?scope=negative|all - Drop failed lookups (default)
+    or the whole geocache. Photos keep their labels until re-resolved.
+    """
+    negative_only = _geocache_scope()
+    if negative_only is None:
+        return jsonify({"error": "scope must be 'negative' or 'all'"}), 400
+    return jsonify({"purged": len(geocache.purge(negative_only))})
+
+
+@app.route('/api/admin/geocache/resolve', methods=['POST'])
+@same_origin
+def resolve_geocache():
+    """
+    POST /api/admin/geocache/resolve {"scope": "negative"|"all"} - Purge the
+    entries and look the affected photos up again in the background.
+    """
+    negative_only = _geocache_scope()
+    if negative_only is None:
+        return jsonify({"error": "scope must be 'negative' or 'all'"}), 400
+    purged = geocache.purge(negative_only)
+    keys = set(purged)
+    if not geocoding_worker.provider.online:
+        # Offline lookups bypass the geocache; pick the photos from the index
+        keys = photo_index.location_keys(negative_only)
+    photos = photo_index.refresh_locations(keys)
+    return jsonify({"purged": len(purged), "photos": photos})
+
+
+def weather_payload(config: Dict[str, Any]) -> Optional[Dict[str, Any]]:
+    """The weather shown on the screensaver, or None without a weather entity."""
+    weather_entity = config.get('weather_entity', '')
+    if not weather_entity:
+        return None
+
+    data = home_assistant.get_state(weather_entity)
+    if not data:
+        return None
+
+    attrs = data.get('attributes', {})
+    return {
+        'condition': data.get('state', ''),
+        'temperature': attrs.get('temperature'),
+        'temperature_unit': attrs.get('temperature_unit', '°C')
+    }
+
 
+def media_payload(config: Dict[str, Any]) -> Optional[Dict[str, Any]]:
+    """The media player state shown on the screensaver, or None without a player."""
+    media_entity = config.get('media_player_entity', '')
     if not media_entity:
-        return jsonify(None)
+        return None
 
-    supervisor_token = os.environ.get('SUPERVISOR_TOKEN', '')
-    if not supervisor_token:
-        logger.warning("No SUPERVISOR_TOKEN available for media API")
-        return jsonify(None)
+    data = home_assistant.get_state(media_entity)
+    if not data:
+        return None
 
-    try:
-        url = f'http://supervisor/core/api/states/{media_entity}'
-        req = urllib.request.Request(url, headers={
-            'Authorization': f'Bearer {supervisor_token}',
-            'Content-Type': 'application/json'
-        })
-        with urllib.request.urlopen(req, timeout=5) as response:
-            data = json.loads(response.read())
-
-        state = data.get('state', '')
-        attrs = data.get('attributes', {})
-
-        entity_picture = attrs.get('entity_picture', '')
-        image_url = None
-        if entity_picture:
-            if entity_picture.startswith('/'):
-                # Relative HA URL — proxy through our endpoint
-                image_url = f'/api/media/image?url={urllib.parse.quote(entity_picture)}'
-            else:
-                # Absolute URL (e.g. Spotify CDN) — use directly
-                image_url = entity_picture
-
-        return jsonify({
-            'state': state,
-            'source': attrs.get('source', ''),
-            'title': attrs.get('media_title', ''),
-            'artist': attrs.get('media_artist', ''),
-            'album': attrs.get('media_album_name', ''),
-            'image_url': image_url,
-            'volume_level': attrs.get('volume_level')
-        })
-    except Exception as e:
-        logger.error(f"Error fetching media: {e}")
-        return jsonify(None)
+    state = data.get('state', '')
+    attrs = data.get('attributes', {})
+
+    entity_picture = attrs.get('entity_picture', '')
+    image_url = None
+    if entity_picture:
+        if entity_picture.startswith('/'):
+            # Relative HA URL — proxy through our endpoint
+            image_url = f'/api/media/image?url={urllib.parse.quote(entity_picture)}'
+        else:
+            # Absolute URL (e.g. Spotify CDN) — use directly
+            image_url = entity_picture
+
+    return {
+        'state': state,
+        'source': attrs.get('source', ''),
+        'title': attrs.get('media_title', ''),
+        'artist': attrs.get('media_artist', ''),
+        'album': attrs.get('media_album_name', ''),
+        'image_url': image_url,
+        'volume_level': attrs.get('volume_level')
+    }
+
+
+class StatePublisher:
+    """
+    Pushes weather and media player changes to the event stream. Media
+    players report changes that don't affect what's shown (e.g. the play
+    position), so an event is only sent when the payload itself changed.
+    """
+
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._last: Dict[str, Any] = {}
+
+    def __call__(self, entity_id: str) -> None:
+        config = load_config()
+        if entity_id == config.get('weather_entity'):
+            self._publish('weather', weather_payload(config))
+        if entity_id == config.get('media_player_entity'):
+            self._publish('media', media_payload(config))
+
+    def _publish(self, event: str, payload: Optional[Dict[str, Any]]) -> None:
+        with self._lock:
+            if event in self._last and self._last[event] == payload:
+                return
+            self._last[event] = payload
+        event_bus.publish(event, payload)
+
+
+@app.route('/api/weather', methods=['GET'])
+def get_weather():
+    """GET /api/weather - Current weather from Home Assistant."""
+    return jsonify(weather_payload(load_config()))
+
+
+@app.route('/api/media', methods=['GET'])
+def get_media():
+    """GET /api/media - Return current media player state from Home Assistant."""
+    return jsonify(media_payload(load_config()))
 
 
 @app.route('/api/media/image', methods=['GET'])
@@ -375,26 +3849,10 @@ def _call_media_service(service: str, extra_data: Optional[Dict] = None) -> bool
     if not media_entity:
         return False
 
-    supervisor_token = os.environ.get('SUPERVISOR_TOKEN', '')
-    if not supervisor_token:
-        return False
-
-    try:
-        url = f'http://supervisor/core/api/services/media_player/{service}'
-        body = {"entity_id": media_entity}
-        if extra_data:
-            body.update(extra_data)
-        data = json.dumps(body).encode('utf-8')
-        req = urllib.request.Request(url, data=data, headers={
-            'Authorization': f'Bearer {supervisor_token}',
-            'Content-Type': 'application/json'
-        })
-        with urllib.request.urlopen(req, timeout=5) as response:
-            response.read()
-        return True
-    except Exception as e:
-        logger.error(f"Error calling media_player/{service}: {e}")
-        return False
+    body = {"entity_id": media_entity}
+    if extra_data:
+        body.update(extra_data)
+    return home_assistant.call_service('media_player', service, body)
 
 
 @app.route('/api/media/play_pause', methods=['POST'])
@@ -432,10 +3890,30 @@ def media_volume():
 
 @app.route('/photos/<path:filename>', methods=['GET'])
 def serve_photo(filename: str):
-    """GET /photos/<filename> - Serve a photo file."""
+    """GET /photos/<path> - Serve a photo file, including from nested album folders."""
     config = load_config()
     photos_folder = config.get('photos_folder', '/media')
 
+    # Hidden files and folders are never indexed, so don't serve them either
+    if any(part.startswith('.') for part in filename.split('/')):
+        return jsonify({"error": "File not found"}), 404
+
+    # HEIC/AVIF are converted at full size to a format every browser shows,
+    # and rotated photos are turned upright since not every kiosk browser
+    # honours the EXIF orientation
+    entry = photo_index.get(filename)
+    rotated = entry is not None and 'orientation' in entry.get('exif', {})
+    transcode = Path(filename).suffix.lower() in TRANSCODE_EXTENSIONS
+    if (rotated or transcode) and HAS_PILLOW:
+        fmt = 'webp' if config.get('rendition_format') == 'webp' else 'jpeg'
+        response = _rendition_response(
+            filename, RENDITION_MAX_SIDE, RENDITION_MAX_SIDE, fmt, config
+        )
+        if response is not None:
+            return response
+        if transcode:
+            return jsonify({"error": "Cannot convert photo"}), 415
+
     try:
         # send_from_directory prevents directory traversal attacks automatically
         return send_from_directory(photos_folder, filename)
@@ -446,6 +3924,62 @@ def serve_photo(filename: str):
         return jsonify({"error": "Permission denied"}), 403
 
 
+def _rendition_response(filename: str, width: int, height: int, fmt: str,
+                        config: Dict[str, Any]):
+    """
+    Build the response for a rendition of a photo, served from the rendition
+    cache when possible. Returns an error response for missing or forbidden
+    files, or None if the photo can't be rendered (e.g. animated GIFs).
+    """
+    photos_folder = config.get('photos_folder', '/media')
+    source = safe_join(photos_folder, filename)
+    if source is None or any(part.startswith('.') for part in filename.split('/')):
+        return jsonify({"error": "File not found"}), 404
+
+    try:
+        stat = os.stat(source)
+    except FileNotFoundError:
+        return jsonify({"error": "File not found"}), 404
+    except PermissionError:
+        logger.error(f"Permission denied accessing file: {filename}")
+        return jsonify({"error": "Permission denied"}), 403
+
+    quality = int(config.get('rendition_quality', 80))
+    key = RenditionCache.key(filename, stat, width, height, fmt, quality)
+
+    cached = rendition_cache.get(key, fmt)
+    if cached:
+        return send_file(cached, mimetype=RENDITION_MIMETYPES[fmt], max_age=86400)
+
+    data = render_photo(Path(source), width, height, fmt, quality)
+    if data is None:
+        return None
+
+    max_bytes = int(config.get('rendition_cache_mb', 500)) * 1024 * 1024
+    rendition_cache.put(key, fmt, data, max_bytes)
+    response = Response(data, mimetype=RENDITION_MIMETYPES[fmt])
+    response.cache_control.max_age = 86400
+    return response
+
+
+@app.route('/renditions/<path:filename>', methods=['GET'])
+def serve_rendition(filename: str):
+    """
+    GET /renditions/<path>?w=<px>&h=<px> - Serve a photo resized to fit the
+    requesting screen, upright, without metadata, re-encoded at the configured
+    format and quality. Falls back to the original when no rendition is possible.
+    """
+    config = load_config()
+    fmt = config.get('rendition_format', 'jpeg')
+    if not HAS_PILLOW or fmt not in RENDITION_MIMETYPES:
+        return serve_photo(filename)
+
+    width = _rendition_bucket(request.args.get('w', type=int), 1920)
+    height = _rendition_bucket(request.args.get('h', type=int), 1080)
+    response = _rendition_response(filename, width, height, fmt, config)
+    return response if response is not None else serve_photo(filename)
+
+
 @app.route('/api/demo/config', methods=['GET'])
 def demo_config():
     """GET /api/demo/config - Return demo configuration for local UI testing."""
@@ -502,6 +4036,29 @@ def serve_static(path: str):
 # APPLICATION ENTRY POINT
 # ============================================================================
 
+def start_background_services() -> None:
+    """Start the threads that keep server-side state current."""
+    config = load_config()
+    geocoding_worker.start(make_geocoding_provider(config), config)
+    home_assistant.add_listener(StatePublisher())
+    presence_monitor.start(config)
+    home_assistant.start([
+        config.get('weather_entity', ''),
+        config.get('media_player_entity', ''),
+        *presence_monitor.entities,
+    ])
+    screens.load()
+    mqtt_bridge.start(config)
+    atexit.register(playback_queues.save)
+    PhotoWatcher(
+        photo_index,
+        config.get('photos_folder', '/media'),
+        int(config.get('max_folder_depth', 10))
+    ).start()
+
+
+start_background_services()
+
 if __name__ == '__main__':
     config = load_config()
     logger.info("=" * 60)
@@ -4,20 +4,87 @@
 IDLE_TIMEOUT=$(bashio::config 'idle_timeout_seconds')
 SLIDE_INTERVAL=$(bashio::config 'slide_interval_seconds')
 PHOTOS_SOURCE=$(bashio::config 'photos_source')
+MAX_FOLDER_DEPTH=$(bashio::config 'max_folder_depth' 10)
+RENDITION_FORMAT=$(bashio::config 'rendition_format' 'jpeg')
+RENDITION_QUALITY=$(bashio::config 'rendition_quality' 80)
+RENDITION_CACHE_MB=$(bashio::config 'rendition_cache_mb' 500)
+VIDEO_ENABLED=$(bashio::config 'video_enabled' 'true')
+VIDEO_MAX_SECONDS=$(bashio::config 'video_max_seconds' 30)
+VIDEO_LONG_CLIP_ACTION=$(bashio::config 'video_long_clip_action' 'trim')
+GEOCODING_PROVIDER=$(bashio::config 'geocoding_provider' 'nominatim')
+GEOCODING_URL=$(bashio::config 'geocoding_url' '')
+GEOCODING_DATABASE=$(bashio::config 'geocoding_database' '')
+GEOCODING_PRECISION=$(bashio::config 'geocoding_precision' 2)
+GEOCODING_LABEL=$(bashio::config 'geocoding_label' 'city')
+GEOCODING_RETRY_HOURS=$(bashio::config 'geocoding_retry_hours' 24)
+# List option; passed through as a JSON array
+PHOTO_FILTERS=$(jq -c '.photo_filters // []' /data/options.json)
+PHOTO_SELECTION=$(bashio::config 'photo_selection' 'all')
+ON_THIS_DAY_WINDOW=$(bashio::config 'on_this_day_window' 'day')
+ON_THIS_DAY_MIN_PHOTOS=$(bashio::config 'on_this_day_min_photos' 5)
+SHUFFLE_QUEUE=$(bashio::config 'shuffle_queue' 'per_screen')
+SHUFFLE_FAVORITE_WEIGHT=$(bashio::config 'shuffle_favorite_weight' 1)
+SHUFFLE_RATING_WEIGHT=$(bashio::config 'shuffle_rating_weight' 1)
+SHUFFLE_RECENT_WEIGHT=$(bashio::config 'shuffle_recent_weight' 1)
+SHUFFLE_RECENT_DAYS=$(bashio::config 'shuffle_recent_days' 30)
+DUPLICATE_SUPPRESSION=$(bashio::config 'duplicate_suppression' 'true')
+DUPLICATE_DISTANCE=$(bashio::config 'duplicate_distance' 6)
+TRASH_FOLDER=$(bashio::config 'trash_folder' '')
+PHOTO_LAYOUT=$(bashio::config 'photo_layout' 'single')
+PHOTO_FIT=$(bashio::config 'photo_fit' 'contain')
+TRANSITION=$(bashio::config 'transition' 'fade')
+TRANSITION_SECONDS=$(bashio::config 'transition_seconds' 1)
+SHOW_CAPTION=$(bashio::config 'show_caption' 'true')
 CLOCK_POSITION=$(bashio::config 'clock_position')
 WEATHER_ENTITY=$(bashio::config 'weather_entity')
 MEDIA_PLAYER_ENTITY=$(bashio::config 'media_player_entity')
 MEDIA_PLAYER_SOURCES=$(bashio::config 'media_player_sources')
+PRESENCE_ENTITY=$(bashio::config 'presence_entity' '')
+MOTION_ENTITY=$(bashio::config 'motion_entity' '')
+SLEEP_AFTER_MINUTES=$(bashio::config 'sleep_after_minutes' 30)
+SLEEP_MODE=$(bashio::config 'sleep_mode' 'black')
+MQTT_DISCOVERY=$(bashio::config 'mqtt_discovery' 'true')
+MQTT_HOST=$(bashio::config 'mqtt_host' '')
+MQTT_PORT=$(bashio::config 'mqtt_port' 1883)
+MQTT_USERNAME=$(bashio::config 'mqtt_username' '')
+MQTT_PASSWORD=$(bashio::config 'mqtt_password' '')
+
+# Without a broker of its own, use the Mosquitto add-on's
+if bashio::var.true "${MQTT_DISCOVERY}" && bashio::var.is_empty "${MQTT_HOST}" \
+    && bashio::services.available 'mqtt'; then
+    MQTT_HOST=$(bashio::services 'mqtt' 'host')
+    MQTT_PORT=$(bashio::services 'mqtt' 'port')
+    MQTT_USERNAME=$(bashio::services 'mqtt' 'username')
+    MQTT_PASSWORD=$(bashio::services 'mqtt' 'password')
+fi
+
+# Free-text options; let jq quote them for the JSON below
+GEOCODING_URL_JSON=$(jq -n --arg value "${GEOCODING_URL}" '$value')
+GEOCODING_DATABASE_JSON=$(jq -n --arg value "${GEOCODING_DATABASE}" '$value')
+TRASH_FOLDER_JSON=$(jq -n --arg value "${TRASH_FOLDER}" '$value')
+MQTT_USERNAME_JSON=$(jq -n --arg value "${MQTT_USERNAME}" '$value')
+MQTT_PASSWORD_JSON=$(jq -n --arg value "${MQTT_PASSWORD}" '$value')
 
 # Log startup information
 bashio::log.info "Starting Home Assistant Screensaver..."
 bashio::log.info "Idle timeout: ${IDLE_TIMEOUT} seconds"
 bashio::log.info "Slide interval: ${SLIDE_INTERVAL} seconds"
+bashio::log.info "Transition: ${TRANSITION} (${TRANSITION_SECONDS} seconds)"
 bashio::log.info "Clock position: ${CLOCK_POSITION}"
 bashio::log.info "Weather entity: ${WEATHER_ENTITY}"
 bashio::log.info "Media player entity: ${MEDIA_PLAYER_ENTITY}"
 bashio::log.info "Media player sources: ${MEDIA_PLAYER_SOURCES}"
+bashio::log.info "Presence: ${PRESENCE_ENTITY:-none}, motion: ${MOTION_ENTITY:-none} (${SLEEP_MODE} after ${SLEEP_AFTER_MINUTES} minutes)"
+bashio::log.info "MQTT discovery: ${MQTT_DISCOVERY} (broker: ${MQTT_HOST:-none}:${MQTT_PORT})"
 bashio::log.info "Photos source: ${PHOTOS_SOURCE}"
+bashio::log.info "Max folder depth: ${MAX_FOLDER_DEPTH}"
+bashio::log.info "Renditions: ${RENDITION_FORMAT} at quality ${RENDITION_QUALITY}, cache ${RENDITION_CACHE_MB} MB"
+bashio::log.info "Video clips: ${VIDEO_ENABLED} (max ${VIDEO_MAX_SECONDS}s, ${VIDEO_LONG_CLIP_ACTION} longer clips)"
+bashio::log.info "Geocoding provider: ${GEOCODING_PROVIDER} (${GEOCODING_LABEL} labels)"
+bashio::log.info "Photo filters: ${PHOTO_FILTERS}"
+bashio::log.info "Photo selection: ${PHOTO_SELECTION}, layout: ${PHOTO_LAYOUT}, fit: ${PHOTO_FIT}"
+bashio::log.info "Duplicate suppression: ${DUPLICATE_SUPPRESSION} (distance ${DUPLICATE_DISTANCE})"
+bashio::log.info "Shuffle: ${SHUFFLE_QUEUE} (favourites x${SHUFFLE_FAVORITE_WEIGHT}, rating x${SHUFFLE_RATING_WEIGHT}, last ${SHUFFLE_RECENT_DAYS} days x${SHUFFLE_RECENT_WEIGHT})"
 
 # Determine photos folder based on configuration
 case "${PHOTOS_SOURCE}" in
@@ -43,12 +110,51 @@ cat > /app/config.json <<EOF
   "home_assistant_url": "http://homeassistant.local:8123",
   "photos_folder": "${PHOTOS_FOLDER}",
   "photos_source": "${PHOTOS_SOURCE}",
+  "max_folder_depth": ${MAX_FOLDER_DEPTH},
+  "rendition_format": "${RENDITION_FORMAT}",
+  "rendition_quality": ${RENDITION_QUALITY},
+  "rendition_cache_mb": ${RENDITION_CACHE_MB},
+  "video_enabled": ${VIDEO_ENABLED},
+  "video_max_seconds": ${VIDEO_MAX_SECONDS},
+  "video_long_clip_action": "${VIDEO_LONG_CLIP_ACTION}",
+  "geocoding_provider": "${GEOCODING_PROVIDER}",
+  "geocoding_url": ${GEOCODING_URL_JSON},
+  "geocoding_database": ${GEOCODING_DATABASE_JSON},
+  "geocoding_precision": ${GEOCODING_PRECISION},
+  "geocoding_label": "${GEOCODING_LABEL}",
+  "geocoding_retry_hours": ${GEOCODING_RETRY_HOURS},
   "idle_timeout_seconds": ${IDLE_TIMEOUT},
   "slide_interval_seconds": ${SLIDE_INTERVAL},
+  "photo_filters": ${PHOTO_FILTERS},
+  "photo_selection": "${PHOTO_SELECTION}",
+  "on_this_day_window": "${ON_THIS_DAY_WINDOW}",
+  "on_this_day_min_photos": ${ON_THIS_DAY_MIN_PHOTOS},
+  "shuffle_queue": "${SHUFFLE_QUEUE}",
+  "shuffle_favorite_weight": ${SHUFFLE_FAVORITE_WEIGHT},
+  "shuffle_rating_weight": ${SHUFFLE_RATING_WEIGHT},
+  "shuffle_recent_weight": ${SHUFFLE_RECENT_WEIGHT},
+  "shuffle_recent_days": ${SHUFFLE_RECENT_DAYS},
+  "duplicate_suppression": ${DUPLICATE_SUPPRESSION},
+  "duplicate_distance": ${DUPLICATE_DISTANCE},
+  "trash_folder": ${TRASH_FOLDER_JSON},
+  "photo_layout": "${PHOTO_LAYOUT}",
+  "photo_fit": "${PHOTO_FIT}",
+  "transition": "${TRANSITION}",
+  "transition_seconds": ${TRANSITION_SECONDS},
+  "show_caption": ${SHOW_CAPTION},
   "clock_position": "${CLOCK_POSITION}",
   "weather_entity": "${WEATHER_ENTITY}",
   "media_player_entity": "${MEDIA_PLAYER_ENTITY}",
-  "media_player_sources": "${MEDIA_PLAYER_SOURCES}"
+  "media_player_sources": "${MEDIA_PLAYER_SOURCES}",
+  "presence_entity": "${PRESENCE_ENTITY}",
+  "motion_entity": "${MOTION_ENTITY}",
+  "sleep_after_minutes": ${SLEEP_AFTER_MINUTES},
+  "sleep_mode": "${SLEEP_MODE}",
+  "mqtt_discovery": ${MQTT_DISCOVERY},
+  "mqtt_host": "${MQTT_HOST}",
+  "mqtt_port": ${MQTT_PORT},
+  "mqtt_username": ${MQTT_USERNAME_JSON},
+  "mqtt_password": ${MQTT_PASSWORD_JSON}
 }
 EOF
 
@@ -56,11 +162,16 @@ bashio::log.info "Configuration file created"
 bashio::log.info "Starting Python server..."
 
 # Start the Python application using Gunicorn
+# A single worker owns the photo index, folder watcher and event streams.
+# Each connected screen holds one thread open for /api/events (at most
+# EVENTS_MAX_STREAMS in app.py); the rest serve photos, API calls and
+# remote control requests waiting for screens to acknowledge
+MAX_SCREENS=20
 exec gunicorn \
     --bind 0.0.0.0:8080 \
-    --workers 2 \
+    --workers 1 \
     --worker-class gthread \
-    --threads 4 \
+    --threads $((MAX_SCREENS + 12)) \
     --timeout 120 \
     --access-logfile - \
     --error-logfile - \
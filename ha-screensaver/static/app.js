@@ -1,10 +1,21 @@
 class ScreensaverApp {
   constructor() {
     this.config = null;
+    this.serverConfig = {};
     this.photos = [];
     this.currentSlideIndex = 0;
     this.slideHistory = [];
-    this.photoExif = {};
+    this.ring = [];
+    this.currentSlide = null;
+    this.lastSlide = null;
+    this.preloaded = null;
+    this.advancing = false;
+    this.upcoming = [];
+    this.queueRequest = null;
+    this.album = null;
+    this.events = null;
+    this.streamConnected = false;
+    this.liveState = false;
     this.weather = null;
     this.media = null;
     this.idleTimer = null;
@@ -12,14 +23,37 @@ class ScreensaverApp {
     this.clockInterval = null;
     this.weatherInterval = null;
     this.mediaInterval = null;
+    this.photoMenuTimeout = null;
+    this.transitionEffect = 'fade';
     this.lastIframeRefresh = Date.now();
     this.isScreensaverActive = false;
     this.isMediaMode = false;
+    this.isSleeping = false;
+    this.occupied = false;
     this.demoMode = new URLSearchParams(window.location.search).has('demo');
+    this.screenId = this.loadScreenId();
 
     this.init();
   }
 
+  loadScreenId() {
+    // ?screen=kitchen names a screen; otherwise each browser gets a random id.
+    // Only named screens show up in Home Assistant
+    const param = new URLSearchParams(window.location.search).get('screen');
+    this.screenNamed = Boolean(param);
+    if (param) return param.slice(0, 64);
+    try {
+      let id = localStorage.getItem('screensaver-screen-id');
+      if (!id) {
+        id = Math.random().toString(36).slice(2, 10);
+        localStorage.setItem('screensaver-screen-id', id);
+      }
+      return id;
+    } catch (e) {
+      return 'default';
+    }
+  }
+
   apiUrl(path) {
     return this.demoMode ? `api/demo/${path}` : `api/${path}`;
   }
@@ -27,8 +61,12 @@ class ScreensaverApp {
   async init() {
     await this.loadConfig();
     await this.loadPhotos();
+    await this.fillQueue();
+    this.subscribeToEvents();
+    this.scheduleDailyResync();
     this.setupEventListeners();
     this.setupMediaControls();
+    this.setupSleepOverlay();
     this.setupIdleDetection();
 
     // Set the iframe source to Home Assistant URL
@@ -39,7 +77,8 @@ class ScreensaverApp {
   async loadConfig() {
     try {
       const response = await fetch(this.apiUrl('config'));
-      this.config = await response.json();
+      this.serverConfig = await response.json();
+      this.config = { ...this.serverConfig };
 
       // Set default slide interval if not present
       if (!this.config.slide_interval_seconds) {
@@ -49,6 +88,7 @@ class ScreensaverApp {
       console.log('Config loaded:', this.config);
     } catch (error) {
       console.error('Error loading config:', error);
+      this.serverConfig = {};
       this.config = {
         home_assistant_url: 'http://homeassistant.local:8123',
         photos_folder: './photos',
@@ -69,7 +109,7 @@ class ScreensaverApp {
         console.log('Demo mode: using placeholder photo');
         return;
       }
-      const response = await fetch('api/photos');
+      const response = await fetch(this.photosUrl());
       const data = await response.json();
       // API returns [{url, exif}, ...] -- store full objects
       this.photos = data;
@@ -80,17 +120,394 @@ class ScreensaverApp {
     }
   }
 
+  photosUrl() {
+    return this.album ? `api/photos?album=${encodeURIComponent(this.album)}` : 'api/photos';
+  }
+
+  inAlbum(photo) {
+    return !this.album || photo.album === this.album || (photo.album || '').startsWith(`${this.album}/`);
+  }
+
+  fillQueue() {
+    // The server shuffles; keep a few of its picks buffered
+    if (this.demoMode || this.queueRequest || this.upcoming.length >= 3) return this.queueRequest;
+    const shape = window.innerWidth >= window.innerHeight ? 'landscape' : 'portrait';
+    let url = `api/queue?screen=${encodeURIComponent(this.screenId)}&count=10&shape=${shape}`;
+    if (this.album) url += `&album=${encodeURIComponent(this.album)}`;
+    this.queueRequest = fetch(url)
+      .then(response => (response.ok ? response.json() : []))
+      .then(photos => {
+        photos.forEach(({ partner, ...photo }) => {
+          this.upsertPhoto(photo);
+          if (partner) this.upsertPhoto(partner);
+        });
+        this.upcoming.push(...photos.map(photo => ({ url: photo.url, partner: photo.partner || null })));
+      })
+      .catch(error => console.error('Error loading playback queue:', error))
+      .finally(() => { this.queueRequest = null; });
+    return this.queueRequest;
+  }
+
+  takeQueued() {
+    let index = -1;
+    let partner = null;
+    while (this.upcoming.length > 0 && index < 0) {
+      const next = this.upcoming.shift();
+      index = this.photos.findIndex(p => p.url === next.url);
+      if (index === this.currentSlideIndex && this.photos.length > 1) index = -1;
+      partner = next.partner;
+    }
+    this.fillQueue();
+    if (index >= 0) return { index, partner };
+
+    // Queue unavailable: fall back to a random slide other than the current one
+    if (this.photos.length <= 1) return { index: 0, partner: null };
+    do {
+      index = Math.floor(Math.random() * this.photos.length);
+    } while (index === this.currentSlideIndex);
+    return { index, partner: null };
+  }
+
+  subscribeToEvents(reconnect = false) {
+    if (this.demoMode || !window.EventSource) return;
+
+    // EventSource reconnects on its own; after a reconnect, resync the photo
+    // list in case library changes were missed while disconnected
+    let connectedBefore = reconnect;
+    const named = this.screenNamed ? '&named=1' : '';
+    this.events = new EventSource(`api/events?screen=${encodeURIComponent(this.screenId)}${named}`);
+    this.events.addEventListener('open', () => {
+      if (connectedBefore) this.resyncPhotos();
+      connectedBefore = true;
+      this.streamConnected = true;
+      this.updatePolling();
+      this.reportState();
+    });
+    this.events.addEventListener('error', () => {
+      this.streamConnected = false;
+      this.liveState = false;
+      this.updatePolling();
+      // A refused stream (e.g. too many screens) is not retried by the
+      // browser; try again later ourselves
+      if (this.events.readyState === EventSource.CLOSED) {
+        this.events.close();
+        setTimeout(() => this.subscribeToEvents(true), 30000);
+      }
+    });
+    this.events.addEventListener('photos', (e) => {
+      this.applyPhotoChanges(JSON.parse(e.data));
+    });
+    this.events.addEventListener('config', (e) => {
+      this.applyConfig(JSON.parse(e.data));
+    });
+    this.events.addEventListener('home_assistant', (e) => {
+      this.liveState = JSON.parse(e.data).connected;
+      this.updatePolling();
+    });
+    this.events.addEventListener('weather', (e) => {
+      this.applyWeather(JSON.parse(e.data));
+    });
+    this.events.addEventListener('media', (e) => {
+      this.applyMedia(JSON.parse(e.data));
+    });
+    this.events.addEventListener('command', (e) => {
+      this.handleCommand(JSON.parse(e.data));
+    });
+    this.events.addEventListener('presence', (e) => {
+      this.applyPresence(JSON.parse(e.data));
+    });
+  }
+
+  applyPresence(presence) {
+    if (!presence) return;
+    const wasOccupied = this.occupied;
+    this.occupied = presence.occupied;
+
+    if (presence.sleep) {
+      this.sleep();
+    } else {
+      this.wake();
+    }
+
+    if (presence.occupied) {
+      // Someone is there: hold the screensaver off
+      clearTimeout(this.idleTimer);
+      if (this.isScreensaverActive) this.stopScreensaver();
+    } else if (wasOccupied && !this.isScreensaverActive) {
+      this.setupIdleDetection();
+    }
+  }
+
+  sleep() {
+    if (this.isSleeping) return;
+    console.log('Room empty, sleeping');
+    this.isSleeping = true;
+    const overlay = document.getElementById('sleep-overlay');
+    overlay.classList.toggle('dim', this.config.sleep_mode === 'dim');
+    overlay.classList.add('active');
+
+    // Nothing moves while the screen sleeps
+    clearInterval(this.slideInterval);
+    this.slideInterval = null;
+    this.pauseVideos();
+  }
+
+  wake() {
+    if (!this.isSleeping) return;
+    console.log('Waking up');
+    this.isSleeping = false;
+    document.getElementById('sleep-overlay').classList.remove('active');
+    if (this.isScreensaverActive && !this.isMediaMode) {
+      this.resetSlideTimer();
+      this.onSlideShown(this.currentSlide);
+    }
+  }
+
+  setupSleepOverlay() {
+    // Touching a sleeping screen wakes it until the room next changes
+    const overlay = document.getElementById('sleep-overlay');
+    ['mousedown', 'touchstart'].forEach(evt => {
+      overlay.addEventListener(evt, (e) => {
+        e.preventDefault();
+        e.stopPropagation();
+        this.wake();
+      });
+    });
+  }
+
+  async handleCommand(data) {
+    // Commands without a screen are for every screen
+    if (data.screen && data.screen !== this.screenId) return;
+    console.log(`Remote command: ${data.command}`);
+    let error = null;
+    try {
+      await this.runCommand(data);
+    } catch (e) {
+      console.error(`Error running remote command '${data.command}':`, e);
+      error = e.message;
+    }
+    if (data.id) this.acknowledgeCommand(data.id, error);
+  }
+
+  async runCommand({ command, album, photo }) {
+    if (command !== 'stop') this.wake();
+    switch (command) {
+      case 'start':
+        if (this.isScreensaverActive) return;
+        if (this.photos.length === 0) throw new Error('No photos to show');
+        await this.startScreensaver();
+        return;
+      case 'stop':
+        if (this.isScreensaverActive) this.stopScreensaver();
+        return;
+      case 'next':
+      case 'previous':
+        if (!this.isScreensaverActive) throw new Error('Screensaver is not running');
+        await (command === 'next' ? this.nextSlide() : this.previousSlide());
+        this.resetSlideTimer();
+        return;
+      case 'album':
+        await this.switchAlbum(album);
+        return;
+      case 'photo':
+        this.upsertPhoto(photo);
+        if (this.isScreensaverActive) {
+          await this.nextSlide(photo);
+          this.resetSlideTimer();
+        } else {
+          await this.startScreensaver(photo);
+        }
+        if (this.photos[this.currentSlideIndex]?.url !== photo.url) throw new Error('Photo could not be shown');
+        return;
+      default:
+        throw new Error(`Unknown command '${command}'`);
+    }
+  }
+
+  async switchAlbum(album) {
+    // Play only this album (or everything again for ''); the server keeps a
+    // separate playback queue for it
+    const previousAlbum = this.album;
+    this.album = album || null;
+    await this.queueRequest;
+    this.upcoming = [];
+    await this.loadPhotos();
+    if (this.photos.length === 0) {
+      this.album = previousAlbum;
+      await this.loadPhotos();
+      throw new Error(`No photos in album '${album}'`);
+    }
+    await this.fillQueue();
+    if (!this.isScreensaverActive) return;
+    this.preloaded = null;
+    await this.nextSlide();
+    this.resetSlideTimer();
+  }
+
+  acknowledgeCommand(id, error) {
+    fetch(`api/screens/${encodeURIComponent(this.screenId)}/ack`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ id, error })
+    }).catch(e => console.error('Error acknowledging command:', e));
+  }
+
+  reportState() {
+    // The server publishes each screen's state to Home Assistant over MQTT
+    if (this.demoMode) return;
+    const photo = this.isScreensaverActive ? this.photos[this.currentSlideIndex] : null;
+    const body = {
+      active: this.isScreensaverActive,
+      photo: photo ? {
+        url: photo.url,
+        album: photo.album,
+        caption: photo.exif?.caption,
+        date: photo.exif?.date,
+        location: photo.exif?.location
+      } : null
+    };
+    fetch(`api/screens/${encodeURIComponent(this.screenId)}/state`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(body)
+    }).catch(error => console.error('Error reporting screen state:', error));
+  }
+
+  applyConfig(config) {
+    // Options only change when the add-on restarts; the stream reconnects
+    // then, and the page reloads to pick up the new options
+    if (Object.keys(this.serverConfig).length === 0) return;
+    const changed = Object.keys({ ...this.serverConfig, ...config })
+      .some(key => JSON.stringify(config[key]) !== JSON.stringify(this.serverConfig[key]));
+    if (!changed) return;
+    console.log('Configuration changed, reloading');
+    window.location.reload();
+  }
+
+  updatePolling() {
+    // Weather and media arrive on the event stream; poll only while the
+    // stream, or the server's connection to Home Assistant, is down
+    const poll = this.isScreensaverActive && !(this.streamConnected && this.liveState);
+    if (!poll) {
+      clearInterval(this.weatherInterval);
+      this.weatherInterval = null;
+      clearInterval(this.mediaInterval);
+      this.mediaInterval = null;
+      return;
+    }
+
+    // Refresh weather every 60 seconds and media player state every 10
+    if (!this.weatherInterval) {
+      this.loadWeather();
+      this.weatherInterval = setInterval(() => this.loadWeather(), 60000);
+    }
+    if (!this.mediaInterval) {
+      this.loadMedia();
+      this.mediaInterval = setInterval(() => this.loadMedia(), 10000);
+    }
+  }
+
+  scheduleDailyResync() {
+    // "On this day" picks different photos each day; fetch them after midnight
+    if (this.demoMode || this.config.photo_selection !== 'on_this_day') return;
+    const midnight = new Date();
+    midnight.setHours(24, 0, 30, 0);
+    setTimeout(async () => {
+      await this.resyncPhotos();
+      this.scheduleDailyResync();
+    }, midnight - Date.now());
+  }
+
+  async resyncPhotos() {
+    try {
+      const response = await fetch(this.photosUrl());
+      if (!response.ok) return;
+      const latest = await response.json();
+      const currentUrls = new Set(this.photos.map(p => p.url));
+      const latestUrls = new Set(latest.map(p => p.url));
+      this.applyPhotoChanges({
+        changed: latest.filter(p => !currentUrls.has(p.url)),
+        removed: this.photos.filter(p => !latestUrls.has(p.url)).map(p => p.url)
+      });
+    } catch (error) {
+      console.error('Error resyncing photos:', error);
+    }
+  }
+
+  applyPhotoChanges({ changed = [], removed = [] }) {
+    removed.forEach(url => this.removePhoto(url));
+    changed.forEach(photo => (this.inAlbum(photo) ? this.upsertPhoto(photo) : this.removePhoto(photo.url)));
+    console.log(`Photo library updated: ${changed.length} changed, ${removed.length} removed`);
+
+    if (this.isScreensaverActive) {
+      if (this.photos.length === 0) {
+        this.stopScreensaver();
+      } else {
+        this.updatePhotoInfo(this.currentSlideIndex);
+      }
+    }
+  }
+
+  upsertPhoto(photo) {
+    const index = this.photos.findIndex(p => p.url === photo.url);
+    if (index >= 0) {
+      this.photos[index] = photo;
+      return;
+    }
+
+    this.photos.push(photo);
+  }
+
+  removePhoto(url) {
+    const index = this.photos.findIndex(p => p.url === url);
+    if (index < 0) return;
+
+    this.photos.splice(index, 1);
+    if (this.currentSlideIndex > index) this.currentSlideIndex--;
+    if (!this.isScreensaverActive) return;
+
+    this.slideHistory = this.slideHistory.filter(entry => entry.url !== url);
+    if (this.preloaded && this.preloaded.url === url) this.preloaded = null;
+    // Move off the photo; nextSlide() finds the new index by URL
+    if (this.currentSlide && this.currentSlide.photoUrl === url && this.photos.length > 0) {
+      this.nextSlide().then(() => this.resetSlideTimer());
+    }
+  }
+
   setupEventListeners() {
     const slideshow = document.getElementById('slideshow');
 
-    const handleSlideshowInteraction = (e) => {
+    // A long press opens the photo menu; a tap acts when it is released
+    let pressTimer = null;
+    let pressX = 0;
+
+    const handlePressStart = (e) => {
       if (!this.isScreensaverActive) return;
       e.preventDefault();
       e.stopPropagation();
 
-      const x = e.touches ? e.touches[0].clientX : e.clientX;
+      // Tapping outside the open menu just closes it
+      if (this.isPhotoMenuOpen()) {
+        this.closePhotoMenu();
+        return;
+      }
+
+      pressX = e.touches ? e.touches[0].clientX : e.clientX;
+      clearTimeout(pressTimer);
+      pressTimer = setTimeout(() => {
+        pressTimer = null;
+        this.openPhotoMenu();
+      }, 600);
+    };
+
+    const handlePressEnd = (e) => {
+      if (!this.isScreensaverActive || !pressTimer) return;
+      e.preventDefault();
+      e.stopPropagation();
+      clearTimeout(pressTimer);
+      pressTimer = null;
 
-      if (x < window.innerWidth * 0.1) {
+      if (pressX < window.innerWidth * 0.1) {
         // Left 10% of screen - go back one image
         this.previousSlide();
         this.resetSlideTimer();
@@ -100,39 +517,145 @@ class ScreensaverApp {
     };
 
     ['mousedown', 'touchstart'].forEach(event => {
-      slideshow.addEventListener(event, handleSlideshowInteraction);
+      slideshow.addEventListener(event, handlePressStart);
     });
+    ['mouseup', 'touchend'].forEach(event => {
+      slideshow.addEventListener(event, handlePressEnd);
+    });
+
+    this.setupPhotoMenu();
+  }
+
+  setupPhotoMenu() {
+    const menu = document.getElementById('photo-menu');
+    ['mousedown', 'touchstart', 'mouseup', 'touchend'].forEach(evt => {
+      menu.addEventListener(evt, (e) => e.stopPropagation());
+    });
+
+    document.getElementById('btn-favorite').addEventListener('click', async (e) => {
+      e.stopPropagation();
+      const photo = this.photos[this.currentSlideIndex];
+      const favorite = !photo.exif?.favorite;
+      if (await this.photoAction('favorite', photo, { favorite })) {
+        photo.exif = { ...photo.exif, favorite };
+      }
+      this.closePhotoMenu();
+    });
+
+    document.getElementById('btn-hide').addEventListener('click', async (e) => {
+      e.stopPropagation();
+      const photo = this.photos[this.currentSlideIndex];
+      this.closePhotoMenu();
+      if (await this.photoAction('hide', photo, { hidden: true })) {
+        this.applyPhotoChanges({ removed: [photo.url] });
+      }
+    });
+
+    const trashButton = document.getElementById('btn-trash');
+    trashButton.addEventListener('click', async (e) => {
+      e.stopPropagation();
+      // Moving files needs a second tap to confirm
+      if (!trashButton.classList.contains('confirm')) {
+        trashButton.classList.add('confirm');
+        trashButton.textContent = 'Tap again to move to trash';
+        return;
+      }
+      const photo = this.photos[this.currentSlideIndex];
+      this.closePhotoMenu();
+      if (await this.photoAction('trash', photo)) {
+        this.applyPhotoChanges({ removed: [photo.url] });
+      }
+    });
+
+    document.getElementById('btn-menu-close').addEventListener('click', (e) => {
+      e.stopPropagation();
+      this.closePhotoMenu();
+    });
+  }
+
+  isPhotoMenuOpen() {
+    return document.getElementById('photo-menu').classList.contains('active');
+  }
+
+  openPhotoMenu() {
+    const photo = this.photos[this.currentSlideIndex];
+    if (!photo || this.demoMode) return;
+
+    // Hold the slideshow on this photo while the menu is open
+    clearInterval(this.slideInterval);
+    this.slideInterval = null;
+    this.pauseVideos();
+
+    document.getElementById('btn-favorite').textContent =
+      photo.exif?.favorite ? '\u2606 Remove favourite' : '\u2605 Favourite';
+    const trashButton = document.getElementById('btn-trash');
+    trashButton.classList.remove('confirm');
+    trashButton.textContent = '\uD83D\uDDD1 Move to trash';
+    document.getElementById('photo-menu').classList.add('active');
+
+    clearTimeout(this.photoMenuTimeout);
+    this.photoMenuTimeout = setTimeout(() => this.closePhotoMenu(), 15000);
+  }
+
+  closePhotoMenu() {
+    clearTimeout(this.photoMenuTimeout);
+    if (!this.isPhotoMenuOpen()) return;
+    document.getElementById('photo-menu').classList.remove('active');
+    if (!this.isScreensaverActive) return;
+    this.resetSlideTimer();
+    this.onSlideShown(this.currentSlide);
+  }
+
+  async photoAction(action, photo, body = {}) {
+    try {
+      const response = await fetch(`api/photos/${action}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ url: photo.url, ...body })
+      });
+      if (!response.ok) throw new Error(`HTTP ${response.status}`);
+      return true;
+    } catch (error) {
+      console.error(`Error running photo action '${action}':`, error);
+      return false;
+    }
+  }
+
+  sendMediaCommand(command, body = null) {
+    const options = { method: 'POST' };
+    if (body) {
+      options.headers = { 'Content-Type': 'application/json' };
+      options.body = JSON.stringify(body);
+    }
+    fetch(`api/media/${command}`, options)
+      .then(() => {
+        // The event stream brings the new state; fetch it only when polling
+        if (this.mediaInterval) this.loadMedia();
+      })
+      .catch(error => console.error(`Error sending media command '${command}':`, error));
   }
 
   setupMediaControls() {
     document.getElementById('btn-play-pause').addEventListener('click', (e) => {
       e.stopPropagation();
-      fetch('api/media/play_pause', { method: 'POST' });
-      // Immediately poll for updated state
-      setTimeout(() => this.loadMedia(), 500);
+      this.sendMediaCommand('play_pause');
     });
 
     document.getElementById('btn-prev').addEventListener('click', (e) => {
       e.stopPropagation();
-      fetch('api/media/previous', { method: 'POST' });
-      setTimeout(() => this.loadMedia(), 500);
+      this.sendMediaCommand('previous');
     });
 
     document.getElementById('btn-next').addEventListener('click', (e) => {
       e.stopPropagation();
-      fetch('api/media/next', { method: 'POST' });
-      setTimeout(() => this.loadMedia(), 500);
+      this.sendMediaCommand('next');
     });
 
     const volumeSlider = document.getElementById('volume-slider');
     volumeSlider.addEventListener('change', (e) => {
       e.stopPropagation();
       const volume = parseInt(e.target.value) / 100;
-      fetch('api/media/volume', {
-        method: 'POST',
-        headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ volume_level: volume })
-      });
+      this.sendMediaCommand('volume', { volume_level: volume });
     });
 
     // Prevent slideshow touch interactions on controls
@@ -152,9 +675,7 @@ class ScreensaverApp {
       const resetIdleTimer = () => {
         if (this.isScreensaverActive) return;
         clearTimeout(this.idleTimer);
-        this.idleTimer = setTimeout(() => {
-          this.startScreensaver();
-        }, this.config.idle_timeout_seconds * 1000);
+        this.idleTimer = setTimeout(() => this.onIdle(), this.config.idle_timeout_seconds * 1000);
       };
 
       const activityEvents = [
@@ -174,15 +695,20 @@ class ScreensaverApp {
 
     // Start/restart the idle timer
     clearTimeout(this.idleTimer);
-    this.idleTimer = setTimeout(() => {
-      this.startScreensaver();
-    }, this.config.idle_timeout_seconds * 1000);
+    this.idleTimer = setTimeout(() => this.onIdle(), this.config.idle_timeout_seconds * 1000);
   }
 
-  startScreensaver() {
+  onIdle() {
+    // Nobody touching the screen doesn't mean nobody is looking at it; the
+    // idle timer restarts once the room is empty
+    if (this.occupied) return;
+    this.startScreensaver();
+  }
+
+  startScreensaver(photo = null) {
     if (this.photos.length === 0) {
       console.log('No photos available for slideshow');
-      return;
+      return Promise.resolve();
     }
 
     console.log('Starting screensaver');
@@ -191,105 +717,353 @@ class ScreensaverApp {
     clearInterval(this.clockInterval);
     const slideshow = document.getElementById('slideshow');
     slideshow.classList.add('active');
+    slideshow.classList.toggle('fit-cover', this.config.photo_fit === 'cover');
 
     // Clear any existing slides but preserve overlay elements
     const preserveIds = [
       'screensaver-clock', 'photo-info', 'now-playing',
-      'media-controls-transport', 'media-controls-volume', 'weather-info'
+      'media-controls-transport', 'media-controls-volume', 'weather-info', 'photo-menu'
     ];
     const preserved = preserveIds.map(id => document.getElementById(id));
     slideshow.innerHTML = '';
     preserved.forEach(el => { if (el) slideshow.appendChild(el); });
 
-    // Pick a random starting slide
-    const startIndex = Math.floor(Math.random() * this.photos.length);
-
-    // Create slides and store EXIF data per index
-    this.photoExif = {};
-    this.photos.forEach((photo, index) => {
-      const slide = document.createElement('div');
-      slide.className = 'slide';
-      if (index === startIndex) slide.classList.add('active');
-
-      const img = document.createElement('img');
-      img.src = photo.url;
-      img.alt = `Photo ${index + 1}`;
-
-      slide.appendChild(img);
+    // A small ring of slides is reused for every photo (current, previous
+    // and the preloaded next one) so only a few images are in memory at once
+    this.ring = Array.from({ length: 3 }, () => {
+      const slide = this.createSlide();
       slideshow.appendChild(slide);
-      this.photoExif[index] = photo.exif || {};
+      return slide;
     });
-
-    this.currentSlideIndex = startIndex;
+    this.currentSlide = null;
+    this.lastSlide = null;
+    this.preloaded = null;
+    this.advancing = false;
     this.slideHistory = [];
+    this.setupTransitions();
 
-    // Apply clock position and show initial photo info
+    // Apply clock position; photo info follows once the first photo is shown
     this.applyClockPosition();
-    this.updatePhotoInfo(startIndex);
-
-    // Load weather data and refresh every 60 seconds
-    this.loadWeather();
-    clearInterval(this.weatherInterval);
-    this.weatherInterval = setInterval(() => {
-      this.loadWeather();
-    }, 60000);
+    this.updatePhotoInfo(-1);
 
-    // Poll media player state every 10 seconds
+    // Show the latest weather and media player state, then keep them current
     this.isMediaMode = false;
-    this.loadMedia();
-    clearInterval(this.mediaInterval);
-    this.mediaInterval = setInterval(() => {
-      this.loadMedia();
-    }, 10000);
+    this.updateWeatherDisplay();
+    this.applyMedia(this.media);
+    this.updatePolling();
 
     // Start the clock
     this.startClock();
 
-    // Change slide based on configured interval
-    this.slideInterval = setInterval(() => {
-      this.nextSlide();
-    }, this.config.slide_interval_seconds * 1000);
+    // Start with the requested photo or the next one in this screen's queue,
+    // then change slide based on configured interval (video clips advance
+    // when they end)
+    return this.nextSlide(photo).then(() => this.resetSlideTimer());
+  }
+
+  createSlide() {
+    const slide = document.createElement('div');
+    slide.className = 'slide';
+    slide.photoUrl = null;
+    return slide;
   }
 
-  nextSlide() {
-    const slides = document.querySelectorAll('.slide');
-    if (slides.length === 0) return;
+  fillSlide(slide, photo, partner) {
+    // Load a photo into a ring slide; resolves to whether it loaded
+    this.onSlideHidden(slide);
+    slide.classList.remove('kenburns-in', 'kenburns-out');
+    slide.replaceChildren();
+    slide.photoUrl = photo.url;
+
+    if (photo.type === 'video') {
+      this.setSlidePartner(slide, null);
+      const video = this.createVideo(photo);
+      slide.appendChild(video);
+      return new Promise(resolve => {
+        video.addEventListener('loadedmetadata', () => resolve(true), { once: true });
+        video.addEventListener('error', () => resolve(false), { once: true });
+      });
+    }
+
+    const img = document.createElement('img');
+    img.src = this.photoSrc(photo);
+    img.alt = 'Photo';
+    this.applyFocus(img, photo);
+    slide.appendChild(img);
+    this.setSlidePartner(slide, partner);
+
+    // A partner that fails to load just leaves the photo on its own
+    const partnerImg = slide.querySelector('img.partner');
+    const partnerReady = partnerImg
+      ? this.decodeImage(partnerImg).catch(() => this.setSlidePartner(slide, null))
+      : null;
+    return Promise.all([this.decodeImage(img), partnerReady]).then(() => true, () => false);
+  }
 
-    if (this.slideHistory.length >= 100) this.slideHistory.shift();
-    this.slideHistory.push(this.currentSlideIndex);
-    slides[this.currentSlideIndex].classList.remove('active');
+  decodeImage(img) {
+    // Decode off the main thread so the transition doesn't stutter
+    if (img.decode) return img.decode();
+    return new Promise((resolve, reject) => {
+      img.addEventListener('load', resolve, { once: true });
+      img.addEventListener('error', reject, { once: true });
+    });
+  }
 
-    // Pick a random slide that's different from the current one
-    let nextIndex;
-    if (slides.length > 1) {
-      do {
-        nextIndex = Math.floor(Math.random() * slides.length);
-      } while (nextIndex === this.currentSlideIndex);
-    } else {
-      nextIndex = 0;
+  withTimeout(ready, seconds = 30) {
+    // A photo that never finishes loading counts as failed
+    return Promise.race([
+      ready,
+      new Promise(resolve => setTimeout(() => resolve(false), seconds * 1000))
+    ]);
+  }
+
+  preloadNext() {
+    const { index, partner } = this.takeQueued();
+    return this.preload(this.photos[index], partner);
+  }
+
+  preload(photo, partner) {
+    if (!photo) return null;
+    const slide = this.ring.find(s => s !== this.currentSlide && s !== this.lastSlide);
+    this.preloaded = {
+      url: photo.url,
+      partner,
+      slide,
+      ready: this.withTimeout(this.fillSlide(slide, photo, partner))
+    };
+    return this.preloaded;
+  }
+
+  applyFocus(img, photo) {
+    // Keep the subject in view when the photo is cropped to fill the screen
+    if (!photo.focus) return;
+    const position = `${(photo.focus.x * 100).toFixed(1)}% ${(photo.focus.y * 100).toFixed(1)}%`;
+    img.style.objectPosition = position;
+    // Ken Burns zooms towards (or away from) the same point
+    img.style.transformOrigin = position;
+  }
+
+  setSlidePartner(slide, partner) {
+    // Paired layout: a second photo shown next to (or below) the slide's own.
+    // The pair is remembered in the history so going back shows it again.
+    slide.querySelector('img.partner')?.remove();
+    slide.classList.toggle('paired', !!partner);
+    slide.partner = partner || null;
+    if (!partner) return;
+
+    const img = document.createElement('img');
+    img.className = 'partner';
+    img.src = this.photoSrc(partner, true);
+    img.alt = 'Paired photo';
+    this.applyFocus(img, partner);
+    slide.appendChild(img);
+  }
+
+  createVideo(photo) {
+    const video = document.createElement('video');
+    video.src = photo.url;
+    video.muted = true;
+    video.playsInline = true;
+    video.preload = 'metadata';
+
+    video.addEventListener('ended', () => this.advanceAfterVideo(video));
+    video.addEventListener('timeupdate', () => {
+      // Trim long clips to the configured cap
+      const maxSeconds = this.config.video_max_seconds || 30;
+      if (this.config.video_long_clip_action === 'trim' && video.currentTime >= maxSeconds) {
+        this.advanceAfterVideo(video);
+      }
+    });
+    // A clip that can't be decoded (e.g. HEVC on some tablets) is skipped
+    video.addEventListener('error', () => this.advanceAfterVideo(video));
+    return video;
+  }
+
+  onSlideShown(slide) {
+    const video = slide && slide.querySelector('video');
+    if (!video || this.isMediaMode || this.isSleeping) return;
+
+    // Clips advance when they end rather than on the slide timer
+    clearInterval(this.slideInterval);
+    this.slideInterval = null;
+    video.currentTime = 0;
+    video.play().catch(error => {
+      console.error('Error playing video:', error);
+      this.advanceAfterVideo(video);
+    });
+  }
+
+  onSlideHidden(slide) {
+    const video = slide && slide.querySelector('video');
+    if (video) video.pause();
+  }
+
+  advanceAfterVideo(video) {
+    const slide = video.parentElement;
+    if (!this.isScreensaverActive || this.isMediaMode) return;
+    if (!slide || !slide.classList.contains('active')) return;
+    video.pause();
+    this.nextSlide().then(() => this.resetSlideTimer());
+  }
+
+  pauseVideos() {
+    document.querySelectorAll('.slide video').forEach(video => video.pause());
+  }
+
+  isCurrentSlideVideo() {
+    return this.photos[this.currentSlideIndex]?.type === 'video';
+  }
+
+  photoSrc(photo, half = false) {
+    // Ask the server for a copy sized to this screen (or half of it, for
+    // paired photos) instead of the original
+    if (!photo.rendition_url || this.config.rendition_format === 'original') return photo.url;
+    const ratio = window.devicePixelRatio || 1;
+    const landscape = window.innerWidth >= window.innerHeight;
+    const width = window.innerWidth / (half && landscape ? 2 : 1);
+    const height = window.innerHeight / (half && !landscape ? 2 : 1);
+    const url = new URL(photo.rendition_url, window.location.href);
+    url.searchParams.set('w', Math.round(width * ratio));
+    url.searchParams.set('h', Math.round(height * ratio));
+    return url.pathname + url.search;
+  }
+
+  async nextSlide(photo = null) {
+    if (!this.isScreensaverActive || this.advancing) return;
+    this.advancing = true;
+    try {
+      if (photo && this.preloaded) {
+        // A chosen photo jumps the queue; the preloaded one comes after it
+        this.upcoming.unshift({ url: this.preloaded.url, partner: this.preloaded.partner });
+        this.preloaded = null;
+      }
+      if (photo) this.preload(photo, null);
+
+      // Photos that fail to load are skipped, a few at a time
+      for (let attempt = 0; attempt < 5; attempt++) {
+        const next = this.preloaded || this.preloadNext();
+        this.preloaded = null;
+        if (!next) return;
+        const loaded = await next.ready;
+        if (!this.isScreensaverActive) return;
+        if (loaded && this.photos.some(p => p.url === next.url)) {
+          this.showSlide(next, false);
+          return;
+        }
+        console.warn('Skipping photo that failed to load:', next.url);
+      }
+    } finally {
+      this.advancing = false;
+      if (this.isScreensaverActive && !this.preloaded) this.preloadNext();
     }
+  }
 
-    this.currentSlideIndex = nextIndex;
-    slides[this.currentSlideIndex].classList.add('active');
+  async previousSlide() {
+    if (!this.isScreensaverActive || this.advancing || !this.lastSlide) return;
+    this.advancing = true;
+    try {
+      while (this.slideHistory.length > 0) {
+        const entry = this.slideHistory.pop();
+        const photo = this.photos.find(p => p.url === entry.url);
+        if (!photo) continue;
+        // The photo just shown is usually still on the last slide
+        const slide = this.lastSlide;
+        const ready = slide.photoUrl === entry.url
+          ? true
+          : await this.withTimeout(this.fillSlide(slide, photo, entry.partner));
+        if (!this.isScreensaverActive) return;
+        if (ready) {
+          this.showSlide({ ...entry, slide }, true);
+          return;
+        }
+      }
+    } finally {
+      this.advancing = false;
+    }
+  }
 
-    this.updateClockColor(slides[this.currentSlideIndex]);
+  showSlide({ url, slide }, reverse) {
+    const previous = this.currentSlide;
+    if (previous) {
+      this.onSlideHidden(previous);
+      if (!reverse) {
+        if (this.slideHistory.length >= 100) this.slideHistory.shift();
+        this.slideHistory.push({ url: previous.photoUrl, partner: previous.partner });
+      }
+    }
+
+    this.currentSlide = slide;
+    this.lastSlide = previous;
+    this.currentSlideIndex = this.photos.findIndex(p => p.url === url);
+    this.transitionSlides(previous, slide, reverse);
+    this.onSlideShown(slide);
+
+    this.updateClockColor(slide);
     this.updatePhotoInfo(this.currentSlideIndex);
+    this.reportState();
   }
 
-  previousSlide() {
-    const slides = document.querySelectorAll('.slide');
-    if (slides.length === 0 || this.slideHistory.length === 0) return;
+  setupTransitions() {
+    const slideshow = document.getElementById('slideshow');
+    const seconds = Number(this.config.transition_seconds) || 1;
+    slideshow.style.setProperty('--transition-duration', `${seconds}s`);
+    // Ken Burns keeps moving until the next slide has fully faded in
+    slideshow.style.setProperty('--kenburns-duration', `${this.config.slide_interval_seconds + seconds * 2}s`);
+    this.setTransitionEffect(this.pickTransition());
+  }
 
-    slides[this.currentSlideIndex].classList.remove('active');
-    this.currentSlideIndex = this.slideHistory.pop();
-    slides[this.currentSlideIndex].classList.add('active');
+  pickTransition() {
+    const effect = this.config.transition || 'fade';
+    if (effect !== 'random') return effect;
+    const effects = ['fade', 'slide', 'kenburns'];
+    return effects[Math.floor(Math.random() * effects.length)];
+  }
 
-    this.updateClockColor(slides[this.currentSlideIndex]);
-    this.updatePhotoInfo(this.currentSlideIndex);
+  setTransitionEffect(effect) {
+    const slideshow = document.getElementById('slideshow');
+    ['fade', 'slide', 'kenburns'].forEach(name => {
+      slideshow.classList.toggle(`transition-${name}`, name === effect);
+    });
+    this.transitionEffect = effect;
+  }
+
+  transitionSlides(from, to, reverse) {
+    // Only opacity and transform are animated so the GPU does the work,
+    // which keeps transitions smooth on low-end tablets
+    const slideshow = document.getElementById('slideshow');
+    if (this.config.transition === 'random') this.setTransitionEffect(this.pickTransition());
+    slideshow.classList.toggle('reverse', reverse);
+
+    if (from && from !== to) {
+      from.classList.remove('active');
+      from.classList.add('leaving');
+      clearTimeout(from.leavingTimeout);
+      const seconds = Number(this.config.transition_seconds) || 1;
+      from.leavingTimeout = setTimeout(() => from.classList.remove('leaving'), seconds * 1000);
+    }
+
+    to.classList.remove('leaving');
+    if (this.transitionEffect === 'slide') {
+      // Park the slide on the side it enters from before it moves in
+      void to.offsetWidth;
+    }
+    this.startKenBurns(to);
+    to.classList.add('active');
+  }
+
+  startKenBurns(slide) {
+    slide.classList.remove('kenburns-in', 'kenburns-out');
+    if (this.transitionEffect !== 'kenburns' || slide.querySelector('video')) return;
+    // Restart the animation: drop it, let the browser notice, add it back
+    void slide.offsetWidth;
+    slide.classList.add(Math.random() < 0.5 ? 'kenburns-in' : 'kenburns-out');
   }
 
   resetSlideTimer() {
     clearInterval(this.slideInterval);
+    this.slideInterval = null;
+    // A video clip advances the slideshow itself when it ends
+    if (this.isCurrentSlideVideo() || this.isSleeping) return;
     this.slideInterval = setInterval(() => {
       this.nextSlide();
     }, this.config.slide_interval_seconds * 1000);
@@ -445,12 +1219,21 @@ class ScreensaverApp {
   }
 
   updatePhotoInfo(slideIndex) {
+    const captionEl = document.getElementById('photo-caption');
     const dateEl = document.getElementById('photo-date');
     const locationEl = document.getElementById('photo-location');
-    if (!dateEl || !locationEl) return;
-    const exif = this.photoExif[slideIndex] || {};
-    dateEl.textContent = exif.date ? `\uD83D\uDCC5 ${exif.date}` : '';
+    const albumEl = document.getElementById('photo-album');
+    if (!captionEl || !dateEl || !locationEl || !albumEl) return;
+    const photo = this.photos[slideIndex] || {};
+    const exif = photo.exif || {};
+    const album = photo.album;
+    captionEl.textContent = this.config.show_caption !== false && exif.caption ? exif.caption : '';
+    const yearsAgo = photo.years_ago
+      ? ` \u00B7 ${photo.years_ago} ${photo.years_ago === 1 ? 'year' : 'years'} ago`
+      : '';
+    dateEl.textContent = exif.date ? `\uD83D\uDCC5 ${exif.date}${yearsAgo}` : '';
     locationEl.textContent = exif.location ? `\uD83D\uDCCD ${exif.location}` : '';
+    albumEl.textContent = album ? `\uD83D\uDCC1 ${album.split('/').join(' \u203A ')}` : '';
   }
 
   async loadWeather() {
@@ -458,13 +1241,17 @@ class ScreensaverApp {
     try {
       const response = await fetch(this.apiUrl('weather'));
       if (!response.ok) return;
-      this.weather = await response.json();
-      this.updateWeatherDisplay();
+      this.applyWeather(await response.json());
     } catch (e) {
       console.error('Error loading weather:', e);
     }
   }
 
+  applyWeather(weather) {
+    this.weather = weather;
+    this.updateWeatherDisplay();
+  }
+
   updateWeatherDisplay() {
     const el = document.getElementById('weather-info');
     if (!el || !this.weather) { if (el) el.innerHTML = ''; return; }
@@ -504,21 +1291,26 @@ class ScreensaverApp {
     try {
       const response = await fetch(this.apiUrl('media'));
       if (!response.ok) return;
-      this.media = await response.json();
-
-      const isActive = this.media && (this.media.state === 'playing' || this.media.state === 'paused');
-      const sourceAllowed = this.isSourceAllowed(this.media?.source);
-
-      if (isActive && sourceAllowed) {
-        this.enterMediaMode();
-      } else if (this.isMediaMode) {
-        this.exitMediaMode();
-      }
+      this.applyMedia(await response.json());
     } catch (e) {
       console.error('Error loading media:', e);
     }
   }
 
+  applyMedia(media) {
+    this.media = media;
+    if (!this.isScreensaverActive) return;
+
+    const isActive = this.media && (this.media.state === 'playing' || this.media.state === 'paused');
+    const sourceAllowed = this.isSourceAllowed(this.media?.source);
+
+    if (isActive && sourceAllowed) {
+      this.enterMediaMode();
+    } else if (this.isMediaMode) {
+      this.exitMediaMode();
+    }
+  }
+
   isSourceAllowed(source) {
     const filter = (this.config.media_player_sources || '').trim();
     if (!filter) return true; // empty = all sources allowed
@@ -535,6 +1327,7 @@ class ScreensaverApp {
     if (!this.isMediaMode) {
       clearInterval(this.slideInterval);
       this.slideInterval = null;
+      this.pauseVideos();
       this.isMediaMode = true;
     }
 
@@ -602,25 +1395,28 @@ class ScreensaverApp {
 
     // Resume photo slideshow
     if (this.isScreensaverActive && !this.slideInterval) {
-      this.slideInterval = setInterval(() => {
-        this.nextSlide();
-      }, this.config.slide_interval_seconds * 1000);
+      this.resetSlideTimer();
 
-      // Update clock color for current photo slide
+      // Update clock color for current photo slide, restart a paused clip
       const activeSlide = document.querySelector('.slide.active');
-      if (activeSlide) this.updateClockColor(activeSlide);
+      if (activeSlide) {
+        this.updateClockColor(activeSlide);
+        this.onSlideShown(activeSlide);
+      }
     }
   }
 
   stopScreensaver() {
     console.log('Stopping screensaver');
     this.isScreensaverActive = false;
+    this.closePhotoMenu();
 
     const slideshow = document.getElementById('slideshow');
     slideshow.classList.remove('active');
 
     clearInterval(this.slideInterval);
     this.slideInterval = null;
+    this.pauseVideos();
 
     clearInterval(this.clockInterval);
     this.clockInterval = null;
@@ -631,6 +1427,7 @@ class ScreensaverApp {
     clearInterval(this.mediaInterval);
     this.mediaInterval = null;
     this.exitMediaMode();
+    this.reportState();
 
     // Restart idle detection
     this.setupIdleDetection();
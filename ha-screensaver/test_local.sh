@@ -42,7 +42,7 @@ mkdir -p test-photos
 echo "✓ Test photos directory created: ./test-photos"
 
 # Check if there are any test photos
-photo_count=$(find test-photos -type f \( -iname "*.jpg" -o -iname "*.png" -o -iname "*.gif" \) | wc -l)
+photo_count=$(find test-photos -type f \( -iname "*.jpg" -o -iname "*.png" -o -iname "*.gif" -o -iname "*.heic" \) | wc -l)
 if [ "$photo_count" -eq 0 ]; then
     echo ""
     echo "⚠️  No photos in test-photos folder."
@@ -0,0 +1,59 @@
+import os
+import sys
+import unittest
+from datetime import date, timedelta
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..'))
+
+from app import _years_ago  # noqa: E402
+
+DAY, WEEK = 0, 3
+
+
+def shown_on(taken, year, window):
+    """The days of the year on which a photo is shown."""
+    day, days = date(year, 1, 1), []
+    while day.year == year:
+        if _years_ago(taken, day, window) is not None:
+            days.append(day)
+        day += timedelta(days=1)
+    return days
+
+
+class LeapDayTest(unittest.TestCase):
+    def test_leap_day_photo_shows_on_february_28th_in_common_years(self):
+        self.assertEqual(shown_on('2020-02-29', 2023, DAY), [date(2023, 2, 28)])
+        self.assertEqual(_years_ago('2020-02-29', date(2023, 2, 28), DAY), 3)
+
+    def test_leap_day_photo_shows_on_leap_day_in_leap_years(self):
+        self.assertEqual(shown_on('2020-02-29', 2024, DAY), [date(2024, 2, 29)])
+
+    def test_february_28th_photo_is_not_shown_on_leap_day(self):
+        self.assertEqual(shown_on('2021-02-28', 2024, DAY), [date(2024, 2, 28)])
+
+    def test_week_window_counts_calendar_days_around_march(self):
+        # Seven days, centred on the anniversary, in both kinds of year
+        self.assertEqual(shown_on('2020-03-01', 2023, WEEK),
+                         [date(2023, 2, 26) + timedelta(days=i) for i in range(7)])
+        self.assertEqual(shown_on('2021-03-01', 2024, WEEK),
+                         [date(2024, 2, 27) + timedelta(days=i) for i in range(7)])
+        self.assertEqual(shown_on('2020-02-29', 2023, WEEK),
+                         [date(2023, 2, 25) + timedelta(days=i) for i in range(7)])
+
+
+class WindowTest(unittest.TestCase):
+    def test_day_window_shows_each_photo_once_a_year(self):
+        for taken in ('2019-01-01', '2019-06-15', '2019-12-31'):
+            self.assertEqual(len(shown_on(taken, 2023, DAY)), 1)
+
+    def test_week_window_wraps_around_new_year(self):
+        self.assertEqual(_years_ago('2020-12-30', date(2023, 1, 2), WEEK), 2)
+        self.assertEqual(_years_ago('2020-01-02', date(2022, 12, 30), WEEK), 3)
+
+    def test_photos_from_this_year_are_not_memories(self):
+        self.assertIsNone(_years_ago('2023-03-01', date(2023, 3, 1), DAY))
+        self.assertIsNone(_years_ago('not a date', date(2023, 3, 1), DAY))
+
+
+if __name__ == '__main__':
+    unittest.main()
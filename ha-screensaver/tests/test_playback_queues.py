@@ -0,0 +1,71 @@
+import os
+import random
+import sys
+import unittest
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..'))
+
+from app import PlaybackQueues  # noqa: E402
+
+TRIALS = 500
+
+
+def photos(count):
+    return [f'/photos/{i:02d}.jpg' for i in range(count)]
+
+
+class FreshQueueTest(unittest.TestCase):
+    def setUp(self):
+        random.seed(1)
+
+    def first_round(self, weights):
+        state = {'remaining': [], 'played': []}
+        PlaybackQueues._reconcile(state, weights)
+        return state['remaining']
+
+    def test_first_round_plays_every_photo_once(self):
+        weights = {url: 1.0 for url in photos(20)}
+        self.assertCountEqual(self.first_round(weights), weights)
+
+    def test_first_photo_is_not_always_last(self):
+        weights = {url: 1.0 for url in photos(20)}
+        first = next(iter(weights))
+        last = sum(self.first_round(weights)[-1] == first for _ in range(TRIALS))
+        # Uniform shuffle: about 1 in 20
+        self.assertLess(last, TRIALS * 0.15)
+
+    def test_favourite_comes_early(self):
+        weights = {url: 1.0 for url in photos(20)}
+        favourite = photos(20)[7]
+        weights[favourite] = 10.0
+        positions = [self.first_round(weights).index(favourite) for _ in range(TRIALS)]
+        self.assertLess(sum(positions) / TRIALS, 5)
+
+
+class MidRoundAdditionTest(unittest.TestCase):
+    def setUp(self):
+        random.seed(2)
+
+    def position_of_new(self, weight):
+        urls = photos(16)
+        state = {'remaining': urls[5:15], 'played': urls[:5]}
+        weights = {url: 1.0 for url in urls}
+        weights[urls[15]] = weight
+        PlaybackQueues._reconcile(state, weights)
+        self.assertEqual(state['played'], urls[:5])
+        self.assertEqual([url for url in state['remaining'] if url != urls[15]], urls[5:15])
+        return state['remaining'].index(urls[15])
+
+    def test_new_photo_can_land_anywhere(self):
+        positions = [self.position_of_new(1.0) for _ in range(TRIALS)]
+        self.assertIn(0, positions)
+        self.assertIn(10, positions)
+        self.assertAlmostEqual(sum(positions) / TRIALS, 5, delta=1)
+
+    def test_heavy_new_photo_comes_early(self):
+        positions = [self.position_of_new(10.0) for _ in range(TRIALS)]
+        self.assertLess(sum(positions) / TRIALS, 2)
+
+
+if __name__ == '__main__':
+    unittest.main()